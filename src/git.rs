@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use git2::{Delta, DiffFindOptions, DiffOptions, Patch, Repository};
+
+/// How a file differs from the `--since` revision.
+#[derive(Clone, Debug)]
+pub(crate) struct GitChange {
+    pub(crate) status: &'static str,
+    // Repository-relative source path for renames and copies
+    pub(crate) from: Option<String>,
+    // Unified diff against the revision, when --with-diff asked for one
+    pub(crate) diff: Option<String>,
+}
+
+impl GitChange {
+    pub(crate) fn label(&self) -> String {
+        match &self.from {
+            Some(from) => format!("{} from {}", self.status, from),
+            None => self.status.to_string(),
+        }
+    }
+}
+
+/// The files changed since a revision across the repositories holding `cli.paths`, keyed by
+/// canonical path.
+#[derive(Default, Debug)]
+pub(crate) struct GitChanges {
+    // Canonical work tree roots; files outside all of them aren't filtered by --since
+    repos: Vec<PathBuf>,
+    files: HashMap<PathBuf, GitChange>,
+}
+
+impl GitChanges {
+    /// Collects the changes between `revision` and the work tree, including untracked files,
+    /// for every repository containing one of `paths`. Paths outside a repository are
+    /// reported and left unfiltered.
+    pub(crate) fn load(paths: &[PathBuf], revision: &str, with_diff: bool, quiet: bool) -> Result<Self> {
+        let mut changes = GitChanges::default();
+
+        for path in paths {
+            let dir = if path.is_dir() { path.as_path() } else { path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")) };
+            let Some((repository, repo)) = Repository::discover(dir).ok()
+                .and_then(|repository| repository.workdir().map(Path::to_path_buf).map(|workdir| (repository, workdir))) else {
+                if !quiet {
+                    eprintln!("Warning: {} is not inside a git repository; --since doesn't apply to it", path.display());
+                }
+                continue;
+            };
+            let repo = repo.canonicalize().unwrap_or(repo);
+            if changes.repos.contains(&repo) {
+                continue;
+            }
+
+            let tree = repository.revparse_single(revision)
+                .and_then(|object| object.peel_to_tree())
+                .map_err(|e| anyhow::anyhow!("Cannot resolve revision '{}' in {}: {}", revision, repo.display(), e.message()))?;
+            let mut options = DiffOptions::new();
+            options.include_untracked(true).recurse_untracked_dirs(true);
+            let mut diff = repository.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))?;
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+            for (index, delta) in diff.deltas().enumerate() {
+                let status = match delta.status() {
+                    Delta::Added | Delta::Untracked => "added",
+                    Delta::Renamed => "renamed",
+                    Delta::Copied => "copied",
+                    Delta::Typechange => "type changed",
+                    Delta::Conflicted => "unmerged",
+                    Delta::Modified => "modified",
+                    // Deleted files have no body left to copy
+                    Delta::Deleted | Delta::Unmodified | Delta::Ignored | Delta::Unreadable => continue,
+                };
+                let Some(file) = delta.new_file().path() else { continue };
+                let from = matches!(delta.status(), Delta::Renamed | Delta::Copied)
+                    .then(|| delta.old_file().path().map(|from| from.to_string_lossy().into_owned()))
+                    .flatten();
+
+                // Untracked files are new in full, so a diff would only repeat the body
+                let diff = if with_diff && delta.status() != Delta::Untracked {
+                    Patch::from_diff(&diff, index)?
+                        .map(|mut patch| patch.to_buf().map(|buf| String::from_utf8_lossy(&buf).into_owned()))
+                        .transpose()?
+                        .filter(|diff| !diff.is_empty())
+                } else {
+                    None
+                };
+                changes.files.insert(repo.join(file), GitChange { status, from, diff });
+            }
+
+            changes.repos.push(repo);
+        }
+
+        Ok(changes)
+    }
+
+    /// Whether `path` is unchanged since the revision. Files outside every repository
+    /// never count as unchanged.
+    pub(crate) fn is_unchanged(&self, path: &Path) -> bool {
+        let Ok(canonical) = path.canonicalize() else {
+            return false;
+        };
+        self.repos.iter().any(|repo| canonical.starts_with(repo)) && !self.files.contains_key(&canonical)
+    }
+
+    pub(crate) fn get(&self, path: &Path) -> Option<&GitChange> {
+        if self.files.is_empty() {
+            return None;
+        }
+        self.files.get(&path.canonicalize().ok()?)
+    }
+}
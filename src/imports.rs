@@ -0,0 +1,208 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use regex::Regex;
+
+const JS_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs", "json"];
+
+/// The statements that pull in other local files, per language.
+struct ImportPatterns {
+    rust_mod: Regex,
+    rust_use: Regex,
+    python_from: Regex,
+    python_import: Regex,
+    js: Regex,
+    c_include: Regex,
+}
+
+impl ImportPatterns {
+    fn new() -> Self {
+        let regex = |pattern: &str| Regex::new(pattern).expect("import pattern is valid");
+        Self {
+            rust_mod: regex(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;"),
+            rust_use: regex(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+((?:crate|super|self)(?:::\w+)+)"),
+            python_from: regex(r"(?m)^\s*from\s+(\.*[\w.]*)\s+import\s+\(?([\w, ]+)"),
+            python_import: regex(r"(?m)^\s*import\s+([\w.]+(?:\s*,\s*[\w.]+)*)"),
+            js: regex(r#"(?:\bfrom\s+|\bimport\s*\(?\s*|\brequire\(\s*)['"](\.{1,2}/[^'"]*)['"]"#),
+            c_include: regex(r#"(?m)^\s*#\s*include\s+"([^"]+)""#),
+        }
+    }
+
+    /// Local files that `path` imports, resolved against the file system.
+    fn resolve(&self, path: &Path, content: &str) -> Vec<PathBuf> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        let mut found = Vec::new();
+
+        match ext {
+            "rs" => {
+                let module_dir = rust_module_dir(path);
+                for caps in self.rust_mod.captures_iter(content) {
+                    found.extend(first_file([module_dir.join(format!("{}.rs", &caps[1])), module_dir.join(&caps[1]).join("mod.rs")]));
+                }
+                for caps in self.rust_use.captures_iter(content) {
+                    found.extend(resolve_rust_use(path, &caps[1]));
+                }
+            }
+            "py" => {
+                for caps in self.python_from.captures_iter(content) {
+                    let module = &caps[1];
+                    let names = caps[2].split(',').map(str::trim).filter(|name| !name.is_empty());
+                    let Some(base) = resolve_python_base(path, module) else { continue };
+                    let relative = module.trim_start_matches('.');
+                    let module_path = if relative.is_empty() { base.clone() } else { base.join(relative.replace('.', "/")) };
+                    found.extend(python_module_file(&module_path));
+                    // `from pkg import submodule` names modules as well as attributes
+                    for name in names {
+                        found.extend(python_module_file(&module_path.join(name)));
+                    }
+                }
+                for caps in self.python_import.captures_iter(content) {
+                    for module in caps[1].split(',').map(str::trim) {
+                        found.extend(python_module_file(&dir.join(module.replace('.', "/"))));
+                    }
+                }
+            }
+            "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" | "vue" | "svelte" => {
+                for caps in self.js.captures_iter(content) {
+                    found.extend(resolve_js(&dir.join(&caps[1])));
+                }
+            }
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => {
+                for caps in self.c_include.captures_iter(content) {
+                    found.extend(first_file([dir.join(&caps[1])]));
+                }
+            }
+            _ => {}
+        }
+
+        found
+    }
+}
+
+/// Walks imports outwards from `entries`, returning every local file reached in discovery
+/// order. Files more than `max_depth` imports away from an entry aren't followed.
+pub(crate) fn follow_imports(entries: &[PathBuf], max_depth: Option<usize>, verbose: bool) -> Result<Vec<PathBuf>> {
+    let patterns = ImportPatterns::new();
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for entry in entries {
+        if !entry.is_file() {
+            anyhow::bail!("--follow-imports entry {} is not a file", entry.display());
+        }
+        queue.push_back((entry.clone(), 0));
+    }
+
+    while let Some((path, depth)) = queue.pop_front() {
+        if !seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+            continue;
+        }
+        files.push(path.clone());
+
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        for import in patterns.resolve(&path, &content).iter().map(|import| normalize(import)) {
+            if verbose {
+                eprintln!("Following import: {} -> {}", path.display(), import.display());
+            }
+            queue.push_back((import, depth + 1));
+        }
+    }
+
+    Ok(files)
+}
+
+// Folds `dir/../file` into `file` so followed paths read like walked ones
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn first_file<const N: usize>(candidates: [PathBuf; N]) -> Option<PathBuf> {
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+// The directory holding the modules a Rust file declares with `mod name;`
+fn rust_module_dir(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some("mod" | "lib" | "main") | None => dir,
+        Some(stem) => dir.join(stem),
+    }
+}
+
+// `crate::a::b`, `super::a` or `self::a`, resolved to the deepest module file that exists
+fn resolve_rust_use(path: &Path, use_path: &str) -> Option<PathBuf> {
+    let mut segments = use_path.split("::");
+    let mut dir = match segments.next()? {
+        "crate" => path.ancestors()
+            .skip(1)
+            .find(|dir| dir.join("lib.rs").is_file() || dir.join("main.rs").is_file())?
+            .to_path_buf(),
+        "super" => rust_module_dir(path).parent()?.to_path_buf(),
+        _ => rust_module_dir(path),
+    };
+
+    let mut found = None;
+    for segment in segments {
+        if segment == "super" {
+            dir = dir.parent()?.to_path_buf();
+            continue;
+        }
+        match first_file([dir.join(format!("{}.rs", segment)), dir.join(segment).join("mod.rs")]) {
+            Some(file) => found = Some(file),
+            None => break,
+        }
+        dir = dir.join(segment);
+    }
+    found
+}
+
+// The package directory a `from ...module import` statement is relative to: the importing
+// file's directory, one level up per extra leading dot
+fn resolve_python_base(path: &Path, module: &str) -> Option<PathBuf> {
+    let dots = module.len() - module.trim_start_matches('.').len();
+    let mut base = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    for _ in 1..dots {
+        base = base.parent()?.to_path_buf();
+    }
+    Some(base)
+}
+
+fn python_module_file(module_path: &Path) -> Option<PathBuf> {
+    first_file([module_path.with_extension("py"), module_path.join("__init__.py")])
+        .filter(|_| module_path.file_name().is_some())
+}
+
+// `./util` may name `./util.ts`, `./util/index.js` or the file itself
+fn resolve_js(base: &Path) -> Option<PathBuf> {
+    if base.is_file() {
+        return Some(base.to_path_buf());
+    }
+    JS_EXTENSIONS.iter()
+        .map(|ext| {
+            let mut candidate = base.as_os_str().to_os_string();
+            candidate.push(".");
+            candidate.push(ext);
+            PathBuf::from(candidate)
+        })
+        .chain(JS_EXTENSIONS.iter().map(|ext| base.join(format!("index.{}", ext))))
+        .find(|candidate| candidate.is_file())
+}
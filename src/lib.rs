@@ -0,0 +1,6240 @@
+//! Collects and formats the contents of a codebase for pasting into an LLM. The `fclip`
+//! binary is a thin wrapper around [`parse_args`], [`run`] and [`watch`]; other tools can build
+//! a [`Config`] with [`Config::from_args`] and use [`collect_files`] and [`format`] directly.
+
+mod git;
+mod imports;
+mod picker;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use ignore::types::{Types, TypesBuilder};
+use ignore::{WalkBuilder, WalkState};
+use glob::Pattern;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Rough token count for `text`, using a different characters-per-token ratio for code,
+/// structured data and prose.
+pub fn estimate_tokens(text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    
+    let char_count = text.len();
+    
+    let chars_per_token = if looks_like_code(text) {
+        2.7  
+    } else if looks_like_structured_data(text) {
+        2.9
+    } else {
+        3.8 
+    };
+    
+    (char_count as f32 / chars_per_token).round() as usize
+}
+
+fn looks_like_code(text: &str) -> bool {
+    let code_patterns = ["{", "}", "fn ", "function", "def ", "class ", "import ", "use ", "->", "=>"];
+    let has_code_keywords = code_patterns.iter().any(|&pattern| text.contains(pattern));
+    
+    let prog_punct_count = text.chars()
+        .filter(|&c| matches!(c, '{' | '}' | '[' | ']' | '(' | ')' | ';' | ':' | '=' | '<' | '>'))
+        .count();
+    let prog_punct_ratio = prog_punct_count as f32 / text.len() as f32;
+    
+    has_code_keywords || prog_punct_ratio > 0.12
+}
+
+fn looks_like_structured_data(text: &str) -> bool {
+    let json_like = text.contains('{') && text.contains('}') && text.contains('"');
+    let toml_like = text.contains('[') && text.contains(']') && text.contains('=');
+    let xml_like = text.contains('<') && text.contains('>');
+    
+    json_like || toml_like || xml_like
+}
+
+const BINARY_SAMPLE_SIZE: usize = 1024;
+
+// `non_printable_percent` is the share of control bytes (besides tab and newlines) above which
+// the sample counts as binary; any NUL byte always does
+fn is_likely_binary(bytes: &[u8], non_printable_percent: f32) -> bool {
+    let sample_size = bytes.len().min(BINARY_SAMPLE_SIZE);
+    let sample = &bytes[0..sample_size];
+    
+    let null_count = sample.iter().filter(|&&b| b == 0).count();
+    let non_printable_count = sample.iter()
+        .filter(|&&b| b < 32 && b != 9 && b != 10 && b != 13)
+        .count();
+    
+    null_count > 0 || (non_printable_count * 100) as f32 > non_printable_percent * sample_size as f32
+}
+
+// UTF-16 without a byte order mark: for mostly-Latin text nearly every high byte is NUL, so
+// NULs cluster on odd offsets (little-endian) or even offsets (big-endian) but not both
+fn detect_bomless_utf16(bytes: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    
+    let sample = &bytes[..bytes.len().min(BINARY_SAMPLE_SIZE)];
+    let pairs = sample.len() / 2;
+    let even_nulls = sample.chunks_exact(2).filter(|pair| pair[0] == 0).count();
+    let odd_nulls = sample.chunks_exact(2).filter(|pair| pair[1] == 0).count();
+    
+    let mostly = |count: usize| count * 10 >= pairs * 9;
+    let rarely = |count: usize| count * 10 <= pairs;
+    
+    if mostly(odd_nulls) && rarely(even_nulls) {
+        Some(encoding_rs::UTF_16LE)
+    } else if mostly(even_nulls) && rarely(odd_nulls) {
+        Some(encoding_rs::UTF_16BE)
+    } else {
+        None
+    }
+}
+
+const DEFAULT_AUTO_EXCLUDES: &[&str] = &[
+    "node_modules", "target", ".git", ".svn", ".hg",
+    "dist", "build", "__pycache__", ".pytest_cache",
+    "coverage", ".coverage", ".nyc_output",
+    "vendor", "deps", ".gradle", ".m2",
+    ".idea", ".vscode", ".vs", "*.log", "*.tmp",
+    "*.cache", "package-lock.json", "yarn.lock",
+    "Cargo.lock", "poetry.lock", "Pipfile.lock",
+    ".DS_Store", "Thumbs.db", "*.swp", "*.swo",
+];
+
+fn resolve_auto_excludes(cli: &Config) -> Vec<String> {
+    let mut excludes: Vec<String> = match &cli.auto_exclude_list {
+        Some(list) => list.iter().map(|p| p.trim().to_string()).collect(),
+        None => DEFAULT_AUTO_EXCLUDES.iter().map(|p| p.to_string()).collect(),
+    };
+    
+    if let Some(additions) = &cli.auto_exclude_add {
+        for addition in additions {
+            let addition = addition.trim();
+            if !excludes.iter().any(|e| e.eq_ignore_ascii_case(addition)) {
+                excludes.push(addition.to_string());
+            }
+        }
+    }
+    
+    if let Some(removals) = &cli.auto_exclude_remove {
+        excludes.retain(|e| !removals.iter().any(|r| e.eq_ignore_ascii_case(r.trim())));
+    }
+    
+    excludes.retain(|e| !e.is_empty());
+    excludes
+}
+
+fn should_auto_exclude(path: &Path, excludes: &[String]) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    let components: Vec<String> = path.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    
+    for exclude in excludes {
+        let exclude = exclude.to_lowercase();
+        if let Some(ext) = exclude.strip_prefix("*.") {
+            if file_name.ends_with(&format!(".{}", ext)) {
+                return true;
+            }
+        } else if components.contains(&exclude) {
+            return true;
+        }
+    }
+    
+    false
+}
+
+fn is_vcs_metadata_dir(path: &Path) -> bool {
+    matches!(
+        path.file_name().and_then(|n| n.to_str()),
+        Some(".git" | ".hg" | ".svn")
+    ) && path.is_dir()
+}
+
+fn should_include_file(path: &Path, include_patterns: &[String]) -> bool {
+    if include_patterns.is_empty() {
+        return true;
+    }
+    
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    
+    for pattern in include_patterns {
+        if !pattern.contains('.') && extension == pattern {
+            return true;
+        }
+
+        if file_name.as_ref() == pattern {
+            return true;
+        }
+
+        if pattern.contains('*') || pattern.contains('?') {
+            if let Ok(glob_pattern) = Pattern::new(pattern) {
+                if glob_pattern.matches(&file_name) {
+                    return true;
+                }
+            }
+        }
+    }
+    
+    false
+}
+
+fn should_exclude_file(path: &Path, exclude_patterns: &[String]) -> bool {
+    if exclude_patterns.is_empty() {
+        return false;
+    }
+    
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    
+    for pattern in exclude_patterns {
+        if !pattern.contains('.') && extension == pattern {
+            return true;
+        }
+
+        if file_name.as_ref() == pattern {
+            return true;
+        }
+        
+        if pattern.contains('*') || pattern.contains('?') {
+            if let Ok(glob_pattern) = Pattern::new(pattern) {
+                if glob_pattern.matches(&file_name) {
+                    return true;
+                }
+            }
+        }
+    }
+    
+    false
+}
+
+fn minify_json(content: &str) -> Option<String> {
+    serde_json::from_str::<serde::de::IgnoredAny>(content).ok()?;
+    
+    let mut result = String::with_capacity(content.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    
+    for ch in content.chars() {
+        if in_string {
+            result.push(ch);
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+        } else if ch == '"' {
+            in_string = true;
+            result.push(ch);
+        } else if !ch.is_whitespace() {
+            result.push(ch);
+        }
+    }
+    
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    
+    Some(result)
+}
+
+fn compress_yaml_flow_spacing(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    
+    for (i, line) in content.lines().enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        
+        let mut quote: Option<char> = None;
+        let mut chars = line.chars().peekable();
+        let mut prev = '\0';
+        
+        while let Some(ch) = chars.next() {
+            match quote {
+                Some(q) => {
+                    if ch == q {
+                        quote = None;
+                    }
+                    result.push(ch);
+                }
+                None if ch == '#' && (prev == '\0' || prev == ' ') => {
+                    result.push(ch);
+                    result.extend(chars.by_ref());
+                }
+                None if ch == '"' || ch == '\'' => {
+                    quote = Some(ch);
+                    result.push(ch);
+                }
+                None if ch == '{' || ch == '[' => {
+                    result.push(ch);
+                    while chars.peek() == Some(&' ') {
+                        chars.next();
+                    }
+                }
+                None if ch == ' ' => {
+                    let mut spaces = 1;
+                    while chars.peek() == Some(&' ') {
+                        chars.next();
+                        spaces += 1;
+                    }
+                    if !matches!(chars.peek(), Some('}') | Some(']')) {
+                        result.push_str(&" ".repeat(spaces));
+                    }
+                }
+                None => result.push(ch),
+            }
+            prev = ch;
+        }
+    }
+    
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    
+    result
+}
+
+// Makefiles need their tab-indented recipes and trailing spaces exactly as written
+fn has_significant_whitespace(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    matches!(name, "Makefile" | "makefile" | "GNUmakefile")
+        || path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mk") || ext.eq_ignore_ascii_case("mak"))
+}
+
+fn compress_file_content(path: &Path, content: &str, level: u8, force: bool) -> String {
+    if has_significant_whitespace(path) && !force {
+        return content.to_string();
+    }
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    
+    match ext.as_str() {
+        "json" if level >= 2 => minify_json(content).unwrap_or_else(|| compress_content(content, &ext, level)),
+        "yml" | "yaml" if level >= 2 => compress_yaml_flow_spacing(&compress_content(content, &ext, level)),
+        _ => compress_content(content, &ext, level),
+    }
+}
+
+// --compress-level, with a bare --compress meaning level 2
+fn compress_level(cli: &Config) -> u8 {
+    cli.compress_level.unwrap_or(if cli.compress { 2 } else { 0 })
+}
+
+fn remove_blank_lines(content: &str) -> String {
+    let mut result: String = content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    
+    if content.ends_with('\n') && !result.is_empty() {
+        result.push('\n');
+    }
+    
+    result
+}
+
+fn process_content(path: &Path, content: &str, cli: &Config, state: &RunState) -> String {
+    if state.binary_files.contains(path) {
+        return content.to_string();
+    }
+    
+    let mut processed = match compress_level(cli) {
+        0 => content.to_string(),
+        level => compress_file_content(path, content, level, cli.force_compress),
+    };
+    
+    if cli.remove_blank_lines {
+        processed = remove_blank_lines(&processed);
+    }
+    
+    processed
+}
+
+fn relative_path_str(file_path: &Path, root: &Path) -> String {
+    let relative = match file_path.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel,
+        _ => Path::new(file_path.file_name().unwrap_or(file_path.as_os_str())),
+    };
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+fn parse_glob_patterns(patterns: &Option<Vec<String>>) -> Result<Vec<Pattern>> {
+    patterns
+        .as_ref()
+        .map(|patterns| {
+            patterns.iter()
+                .map(|p| Pattern::new(p.trim()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+        .map_err(|e| anyhow::anyhow!("Invalid glob pattern: {}", e))
+}
+
+fn find_matching_glob<'a>(relative_path: &str, patterns: &'a [Pattern]) -> Option<&'a Pattern> {
+    patterns.iter().find(|pattern| pattern.matches(relative_path))
+}
+
+// A quote only starts a string when it has a matching closing quote later on
+// the same line. Apostrophes between letters (contractions like "it's" in
+// comments or prose) never do, so they can't switch off whitespace collapsing.
+fn opens_string(chars: &[char], i: usize) -> bool {
+    let quote = chars[i];
+    
+    if quote == '\'' {
+        let prev_is_word = i > 0 && chars[i - 1].is_alphanumeric();
+        let next_is_word = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+        if prev_is_word && next_is_word {
+            return false;
+        }
+    }
+    
+    let mut escaped = false;
+    for &ch in &chars[i + 1..] {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == quote {
+            return true;
+        }
+    }
+    
+    false
+}
+
+/// Compresses `content` the way `--compress-level` does: 0 leaves it alone, 1 strips trailing
+/// whitespace, 2 (`--compress`) also collapses runs of spaces and blank lines, and 3 drops
+/// blank lines entirely. `ext` selects Markdown handling.
+pub fn compress_content(content: &str, ext: &str, level: u8) -> String {
+    match level {
+        0 => content.to_string(),
+        1 => trim_trailing_whitespace(content),
+        2 => collapse_whitespace(content, ext, false),
+        _ => collapse_whitespace(content, ext, true),
+    }
+}
+
+// Keeps each line's own LF or CRLF ending, so files kept with --line-ending preserve stay intact
+fn trim_trailing_whitespace(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        result.push_str(body.trim_end());
+        result.push_str(&line[body.len()..]);
+    }
+    result
+}
+
+// Runs of blank lines shrink to one, or disappear entirely with `drop_blank_lines`
+fn collapse_whitespace(content: &str, ext: &str, drop_blank_lines: bool) -> String {
+    let is_markdown = matches!(ext, "md" | "markdown");
+    let mut result = String::new();
+    let mut open_fence: Option<String> = None;
+    let mut blank_run = 0;
+    
+    for line in content.lines() {
+        if is_markdown {
+            if let Some(fence) = &open_fence {
+                // Fenced code is kept byte-for-byte: indentation and blank lines matter there
+                if is_closing_fence(line, fence) {
+                    open_fence = None;
+                }
+                result.push_str(line);
+                result.push('\n');
+                blank_run = 0;
+                continue;
+            }
+            
+            if let Some(fence) = opening_fence(line) {
+                open_fence = Some(fence);
+                result.push_str(line.trim_end());
+                result.push('\n');
+                blank_run = 0;
+                continue;
+            }
+        }
+        
+        if line.trim().is_empty() {
+            if blank_run < 1 && !drop_blank_lines {
+                result.push('\n');
+            }
+            blank_run += 1;
+            continue;
+        }
+        
+        blank_run = 0;
+        result.push_str(&compress_line(line));
+        result.push('\n');
+    }
+    
+    if !content.ends_with('\n') && result.ends_with('\n') {
+        result.pop();
+    }
+    
+    result
+}
+
+// Returns the fence marker (a run of ``` or ~~~) if `line` opens a fenced code block
+fn opening_fence(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = trimmed.chars().take_while(|c| *c == marker).count();
+    if run < 3 {
+        return None;
+    }
+    
+    Some(marker.to_string().repeat(run))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    let marker = fence.chars().next().unwrap_or('`');
+    trimmed.len() >= fence.len() && trimmed.chars().all(|c| c == marker)
+}
+
+fn compress_line(line: &str) -> String {
+    let mut result = String::new();
+    
+    let leading_whitespace_end = line.chars()
+        .position(|c| c != ' ' && c != '\t')
+        .unwrap_or(line.len());
+    
+    let indentation = &line[..leading_whitespace_end];
+    let content_part = &line[leading_whitespace_end..];
+    
+    result.push_str(&indentation.replace('\t', " "));
+    
+    let chars: Vec<char> = content_part.chars().collect();
+    let mut prev_space = false;
+    let mut in_string = false;
+    let mut string_char = '"';
+    let mut escaped = false;
+    
+    for (i, &ch) in chars.iter().enumerate() {
+        if in_string {
+            result.push(ch);
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == string_char {
+                in_string = false;
+            }
+            prev_space = false;
+            continue;
+        }
+        
+        match ch {
+            '"' | '\'' if opens_string(&chars, i) => {
+                in_string = true;
+                string_char = ch;
+                result.push(ch);
+                prev_space = false;
+            }
+            ' ' | '\t' => {
+                if !prev_space {
+                    result.push(' ');
+                    prev_space = true;
+                }
+            }
+            _ => {
+                result.push(ch);
+                prev_space = false;
+            }
+        }
+    }
+    
+    while result.ends_with(' ') {
+        result.pop();
+    }
+    
+    result
+}
+
+struct TreeEntry<'a> {
+    path: &'a Path,
+    size: usize,
+    tokens: Option<usize>,
+}
+
+// The --include-structure entries for the copied files, plus the files --content-depth left
+// to the tree alone
+fn structure_entries<'a>(files: &'a [(PathBuf, String)], cli: &'a Config, state: &'a RunState) -> Vec<TreeEntry<'a>> {
+    files.iter()
+        .map(|(path, content)| TreeEntry { path, size: content.len(), tokens: Some(file_tokens(path, content, state)) })
+        .chain(state.structure_only_files.iter().map(|path| TreeEntry {
+            path,
+            size: if cli.tree_sizes { fs::metadata(path).map(|m| m.len() as usize).unwrap_or(0) } else { 0 },
+            tokens: None,
+        }))
+        .collect()
+}
+
+#[derive(Default)]
+struct TreeNode {
+    children: BTreeMap<String, TreeNode>,
+    size: usize,
+    tokens: usize,
+}
+
+impl TreeNode {
+    fn insert(&mut self, relative_path: &Path, size: usize, tokens: usize) {
+        let mut node = self;
+        node.size += size;
+        node.tokens += tokens;
+        for component in relative_path.components() {
+            if let std::path::Component::Normal(name) = component {
+                node = node.children.entry(name.to_string_lossy().to_string()).or_default();
+                node.size += size;
+                node.tokens += tokens;
+            }
+        }
+    }
+}
+
+struct TreeGlyphs {
+    branch: &'static str,
+    last_branch: &'static str,
+    vertical: &'static str,
+    blank: &'static str,
+}
+
+const UNICODE_TREE_GLYPHS: TreeGlyphs = TreeGlyphs {
+    branch: "\u{251c}\u{2500}\u{2500} ",
+    last_branch: "\u{2514}\u{2500}\u{2500} ",
+    vertical: "\u{2502}   ",
+    blank: "    ",
+};
+
+const ASCII_TREE_GLYPHS: TreeGlyphs = TreeGlyphs {
+    branch: "|-- ",
+    last_branch: "`-- ",
+    vertical: "|   ",
+    blank: "    ",
+};
+
+struct TreeStyle<'a> {
+    glyphs: &'a TreeGlyphs,
+    show_sizes: bool,
+    show_tokens: bool,
+    max_entries: usize,
+}
+
+impl TreeStyle<'_> {
+    fn annotation(&self, size: usize, tokens: usize) -> String {
+        match (self.show_sizes, self.show_tokens) {
+            (true, true) => format!(" ({}, ~{} tokens)", format_size(size), tokens),
+            (true, false) => format!(" ({})", format_size(size)),
+            _ => String::new(),
+        }
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn generate_directory_tree(roots: &[PathBuf], files: &[TreeEntry], cli: &Config) -> String {
+    let style = TreeStyle {
+        glyphs: if cli.ascii_tree { &ASCII_TREE_GLYPHS } else { &UNICODE_TREE_GLYPHS },
+        show_sizes: cli.tree_sizes,
+        show_tokens: files.iter().all(|entry| entry.tokens.is_some()),
+        max_entries: cli.tree_max_entries,
+    };
+    let mut tree = String::from("## Project Structure\n\n```\n");
+    let mut placed = vec![false; files.len()];
+    
+    for root in roots {
+        let mut root_node = TreeNode::default();
+        let mut file_root = None;
+        
+        for (i, entry) in files.iter().enumerate() {
+            if placed[i] {
+                continue;
+            }
+            if entry.path == root.as_path() {
+                file_root = Some(entry);
+                placed[i] = true;
+            } else if let Ok(relative) = entry.path.strip_prefix(root) {
+                root_node.insert(relative, entry.size, entry.tokens.unwrap_or(0));
+                placed[i] = true;
+            }
+        }
+        
+        if let Some(entry) = file_root {
+            tree.push_str(&format!("{}{}\n", styled_path(root, cli), style.annotation(entry.size, entry.tokens.unwrap_or(0))));
+        } else if !root_node.children.is_empty() {
+            tree.push_str(&format!("{}/{}\n", styled_path(root, cli), style.annotation(root_node.size, root_node.tokens)));
+            add_directory_contents(&mut tree, &root_node, "", &style);
+        }
+    }
+    
+    for (entry, _) in files.iter().zip(&placed).filter(|(_, placed)| !**placed) {
+        tree.push_str(&format!("{}{}\n", styled_path(entry.path, cli), style.annotation(entry.size, entry.tokens.unwrap_or(0))));
+    }
+    
+    tree.push_str("```\n\n");
+    tree
+}
+
+fn add_directory_contents(tree: &mut String, node: &TreeNode, prefix: &str, style: &TreeStyle) {
+    let total = node.children.len();
+    let shown = if style.max_entries == 0 { total } else { total.min(style.max_entries) };
+    let hidden = total - shown;
+    
+    for (i, (name, child)) in node.children.iter().take(shown).enumerate() {
+        let is_last = i == shown - 1 && hidden == 0;
+        let current_prefix = if is_last { style.glyphs.last_branch } else { style.glyphs.branch };
+        let next_prefix = if is_last { style.glyphs.blank } else { style.glyphs.vertical };
+        let annotation = style.annotation(child.size, child.tokens);
+        
+        if child.children.is_empty() {
+            tree.push_str(&format!("{}{}{}{}\n", prefix, current_prefix, name, annotation));
+        } else {
+            tree.push_str(&format!("{}{}{}/{}\n", prefix, current_prefix, name, annotation));
+            add_directory_contents(tree, child, &format!("{}{}", prefix, next_prefix), style);
+        }
+    }
+    
+    if hidden > 0 {
+        tree.push_str(&format!("{}{}... and {} more\n", prefix, style.glyphs.last_branch, hidden));
+    }
+}
+
+fn describe_cargo_dependency(spec: &toml::Value) -> String {
+    let table = match spec {
+        toml::Value::String(version) => return version.clone(),
+        toml::Value::Table(table) => table,
+        _ => return "*".to_string(),
+    };
+    
+    if table.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+        return "workspace".to_string();
+    }
+    
+    if let Some(git) = table.get("git").and_then(|g| g.as_str()) {
+        let reference = ["branch", "tag", "rev"].iter()
+            .find_map(|key| table.get(*key).and_then(|v| v.as_str()).map(|v| format!(" ({} {})", key, v)))
+            .unwrap_or_default();
+        return format!("git {}{}", git, reference);
+    }
+    
+    if let Some(path) = table.get("path").and_then(|p| p.as_str()) {
+        return format!("path {}", path);
+    }
+    
+    table.get("version")
+        .and_then(|v| v.as_str())
+        .unwrap_or("*")
+        .to_string()
+}
+
+fn format_cargo_dependencies(manifest: &toml::Table) -> String {
+    let mut section = String::new();
+    
+    for (key, label) in [("dependencies", ""), ("dev-dependencies", " (dev)"), ("build-dependencies", " (build)")] {
+        if let Some(dependencies) = manifest.get(key).and_then(|d| d.as_table()) {
+            for (name, spec) in dependencies {
+                section.push_str(&format!("- {}: {}{}\n", name, describe_cargo_dependency(spec), label));
+            }
+        }
+    }
+    
+    section
+}
+
+fn format_pyproject_dependencies(manifest: &toml::Table) -> String {
+    let mut section = String::new();
+    let project = manifest.get("project").and_then(|p| p.as_table());
+    
+    if let Some(dependencies) = project.and_then(|p| p.get("dependencies")).and_then(|d| d.as_array()) {
+        for dependency in dependencies.iter().filter_map(|d| d.as_str()) {
+            section.push_str(&format!("- {}\n", dependency));
+        }
+    }
+    
+    if let Some(optional) = project.and_then(|p| p.get("optional-dependencies")).and_then(|d| d.as_table()) {
+        for (extra, dependencies) in optional {
+            for dependency in dependencies.as_array().into_iter().flatten().filter_map(|d| d.as_str()) {
+                section.push_str(&format!("- {} (optional: {})\n", dependency, extra));
+            }
+        }
+    }
+    
+    let poetry_dependencies = manifest.get("tool")
+        .and_then(|t| t.get("poetry"))
+        .and_then(|p| p.get("dependencies"))
+        .and_then(|d| d.as_table());
+    if let Some(dependencies) = poetry_dependencies {
+        for (name, spec) in dependencies {
+            let version = match spec {
+                toml::Value::String(version) => version.clone(),
+                toml::Value::Table(table) => table.get("version")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+                    .or_else(|| table.get("git").and_then(|g| g.as_str()).map(|g| format!("git: {}", g)))
+                    .or_else(|| table.get("path").and_then(|p| p.as_str()).map(|p| format!("path: {}", p)))
+                    .unwrap_or_else(|| "*".to_string()),
+                _ => "*".to_string(),
+            };
+            section.push_str(&format!("- {}: {} (poetry)\n", name, version));
+        }
+    }
+    
+    section
+}
+
+type ManifestFormatter = fn(&str) -> String;
+
+fn format_gemfile_dependencies(content: &str) -> String {
+    let mut section = String::new();
+    
+    for line in content.lines() {
+        let trimmed = line.trim();
+        let Some(rest) = trimmed.strip_prefix("gem ") else {
+            continue;
+        };
+        
+        let parts: Vec<&str> = rest.split(',')
+            .map(|part| part.trim().trim_matches(|c| c == '\'' || c == '"'))
+            .collect();
+        let name = parts[0];
+        let versions: Vec<&str> = parts[1..].iter()
+            .copied()
+            .filter(|part| !part.contains(':') && !part.contains("=>"))
+            .collect();
+        
+        if versions.is_empty() {
+            section.push_str(&format!("- {}\n", name));
+        } else {
+            section.push_str(&format!("- {}: {}\n", name, versions.join(", ")));
+        }
+    }
+    
+    section
+}
+
+fn format_composer_dependencies(content: &str) -> String {
+    let mut section = String::new();
+    let Ok(json) = serde_json::from_str::<Value>(content) else {
+        return section;
+    };
+    
+    for (key, label) in [("require", ""), ("require-dev", " (dev)")] {
+        if let Some(dependencies) = json.get(key).and_then(|d| d.as_object()) {
+            for (name, version) in dependencies {
+                section.push_str(&format!("- {}: {}{}\n", name, version.as_str().unwrap_or("*"), label));
+            }
+        }
+    }
+    
+    section
+}
+
+fn xml_child_text<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn format_pom_dependencies(content: &str) -> String {
+    let mut section = String::new();
+    let Ok(document) = roxmltree::Document::parse(content) else {
+        return section;
+    };
+    
+    for dependency in document.descendants().filter(|n| n.tag_name().name() == "dependency") {
+        let group_id = xml_child_text(dependency, "groupId").unwrap_or("?");
+        let artifact_id = xml_child_text(dependency, "artifactId").unwrap_or("?");
+        let mut line = format!("- {}:{}", group_id, artifact_id);
+        
+        if let Some(version) = xml_child_text(dependency, "version") {
+            line.push_str(&format!(": {}", version));
+        }
+        if let Some(scope) = xml_child_text(dependency, "scope") {
+            line.push_str(&format!(" ({})", scope));
+        }
+        
+        section.push_str(&line);
+        section.push('\n');
+    }
+    
+    section
+}
+
+fn format_gradle_dependencies(content: &str) -> String {
+    let mut section = String::new();
+    let mut depth = 0usize;
+    let mut in_dependencies = false;
+    
+    for line in content.lines() {
+        let trimmed = line.trim();
+        
+        if !in_dependencies {
+            if trimmed.starts_with("dependencies") && trimmed.ends_with('{') {
+                in_dependencies = true;
+                depth = 1;
+            }
+            continue;
+        }
+        
+        let depth_before = depth;
+        depth += trimmed.matches('{').count();
+        depth = depth.saturating_sub(trimmed.matches('}').count());
+        if depth == 0 {
+            in_dependencies = false;
+            continue;
+        }
+        
+        if depth_before == 1 && !trimmed.is_empty() && !trimmed.starts_with("//") && !trimmed.starts_with('}') {
+            section.push_str(&format!("- {}\n", trimmed.trim_end_matches('{').trim_end()));
+        }
+    }
+    
+    section
+}
+
+fn format_csproj_dependencies(content: &str) -> String {
+    let mut section = String::new();
+    let Ok(document) = roxmltree::Document::parse(content) else {
+        return section;
+    };
+    
+    for reference in document.descendants().filter(|n| n.tag_name().name() == "PackageReference") {
+        let Some(name) = reference.attribute("Include") else {
+            continue;
+        };
+        let version = reference.attribute("Version")
+            .or_else(|| xml_child_text(reference, "Version"));
+        
+        match version {
+            Some(version) => section.push_str(&format!("- {}: {}\n", name, version)),
+            None => section.push_str(&format!("- {}\n", name)),
+        }
+    }
+    
+    section
+}
+
+fn find_dependencies(paths: &[PathBuf]) -> String {
+    let mut deps = String::from("## Dependencies\n\n");
+    let mut found_any = false;
+    
+    for path in paths {
+        let search_dir = if path.is_file() {
+            path.parent().unwrap_or(path)
+        } else {
+            path
+        };
+        
+        let package_json = search_dir.join("package.json");
+        if package_json.exists() {
+            if let Ok(content) = fs::read_to_string(&package_json) {
+                if let Ok(json) = serde_json::from_str::<Value>(&content) {
+                    deps.push_str("### JavaScript/Node.js (package.json)\n");
+                    if let Some(dependencies) = json.get("dependencies").and_then(|d| d.as_object()) {
+                        for (name, version) in dependencies {
+                            deps.push_str(&format!("- {}: {}\n", name, version.as_str().unwrap_or("*")));
+                        }
+                    }
+                    deps.push('\n');
+                    found_any = true;
+                }
+            }
+        }
+        
+        let cargo_toml = search_dir.join("Cargo.toml");
+        if cargo_toml.exists() {
+            if let Ok(content) = fs::read_to_string(&cargo_toml) {
+                if let Ok(manifest) = content.parse::<toml::Table>() {
+                    let section = format_cargo_dependencies(&manifest);
+                    if !section.is_empty() {
+                        deps.push_str("### Rust (Cargo.toml)\n");
+                        deps.push_str(&section);
+                        deps.push('\n');
+                        found_any = true;
+                    }
+                }
+            }
+        }
+        
+        let requirements = search_dir.join("requirements.txt");
+        if requirements.exists() {
+            if let Ok(content) = fs::read_to_string(&requirements) {
+                deps.push_str("### Python (requirements.txt)\n");
+                for line in content.lines() {
+                    let trimmed = line.trim();
+                    if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                        deps.push_str(&format!("- {}\n", trimmed));
+                    }
+                }
+                deps.push('\n');
+                found_any = true;
+            }
+        }
+        
+        let pyproject = search_dir.join("pyproject.toml");
+        if pyproject.exists() {
+            if let Ok(content) = fs::read_to_string(&pyproject) {
+                if let Ok(manifest) = content.parse::<toml::Table>() {
+                    let section = format_pyproject_dependencies(&manifest);
+                    if !section.is_empty() {
+                        deps.push_str("### Python (pyproject.toml)\n");
+                        deps.push_str(&section);
+                        deps.push('\n');
+                        found_any = true;
+                    }
+                }
+            }
+        }
+        
+        let go_mod = search_dir.join("go.mod");
+        if go_mod.exists() {
+            if let Ok(content) = fs::read_to_string(&go_mod) {
+                deps.push_str("### Go (go.mod)\n");
+                let lines: Vec<&str> = content.lines().collect();
+                let mut in_require = false;
+                
+                for line in lines {
+                    let trimmed = line.trim();
+                    if trimmed.starts_with("require (") {
+                        in_require = true;
+                        continue;
+                    }
+                    if trimmed == ")" && in_require {
+                        in_require = false;
+                    }
+                    if (in_require || trimmed.starts_with("require ")) && !trimmed.starts_with("//") {
+                        let clean_line = trimmed.replace("require ", "").replace("(", "").trim().to_string();
+                        if !clean_line.is_empty() && clean_line != ")" {
+                            deps.push_str(&format!("- {}\n", clean_line));
+                        }
+                    }
+                }
+                deps.push('\n');
+                found_any = true;
+            }
+        }
+        
+        let manifests: [(&str, &str, ManifestFormatter); 5] = [
+            ("Gemfile", "Ruby (Gemfile)", format_gemfile_dependencies),
+            ("composer.json", "PHP (composer.json)", format_composer_dependencies),
+            ("pom.xml", "Java/Maven (pom.xml)", format_pom_dependencies),
+            ("build.gradle", "Gradle (build.gradle)", format_gradle_dependencies),
+            ("build.gradle.kts", "Gradle (build.gradle.kts)", format_gradle_dependencies),
+        ];
+        
+        for (file_name, heading, format_section) in manifests {
+            if let Ok(content) = fs::read_to_string(search_dir.join(file_name)) {
+                let section = format_section(&content);
+                if !section.is_empty() {
+                    deps.push_str(&format!("### {}\n", heading));
+                    deps.push_str(&section);
+                    deps.push('\n');
+                    found_any = true;
+                }
+            }
+        }
+        
+        if let Ok(entries) = fs::read_dir(search_dir) {
+            let mut csproj_files: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "csproj"))
+                .collect();
+            csproj_files.sort();
+            
+            for csproj in csproj_files {
+                if let Ok(content) = fs::read_to_string(&csproj) {
+                    let section = format_csproj_dependencies(&content);
+                    if !section.is_empty() {
+                        deps.push_str(&format!("### .NET ({})\n", csproj.file_name().unwrap_or_default().to_string_lossy()));
+                        deps.push_str(&section);
+                        deps.push('\n');
+                        found_any = true;
+                    }
+                }
+            }
+        }
+    }
+    
+    if found_any {
+        deps
+    } else {
+        String::new()
+    }
+}
+
+const SECRET_FILE_NAMES: &[&str] = &[
+    ".env", "id_rsa", "id_dsa", "id_ecdsa", "id_ed25519", "credentials.json", "credentials",
+    ".npmrc", ".pypirc", ".netrc", ".git-credentials", ".htpasswd", "secrets.yml", "secrets.yaml",
+];
+
+const SECRET_FILE_EXTENSIONS: &[&str] = &["pem", "key", "p12", "pfx", "jks", "keystore", "kdbx"];
+
+// Names that usually hold credentials, whatever their content. Templates such as
+// `.env.example` are meant to be shared and don't count.
+fn is_secret_filename(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    if let Some(variant) = name.strip_prefix(".env.") {
+        return !matches!(variant, "example" | "sample" | "template" | "dist" | "defaults");
+    }
+    SECRET_FILE_NAMES.contains(&name)
+        || path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| SECRET_FILE_EXTENSIONS.contains(&ext))
+}
+
+fn warn_secret_files(candidates: &[PathBuf]) {
+    let secrets: Vec<&PathBuf> = candidates.iter().filter(|path| is_secret_filename(path)).collect();
+    if secrets.is_empty() {
+        return;
+    }
+    eprintln!("!!! WARNING: {} file(s) with names that suggest secrets are about to be copied:", secrets.len());
+    for path in secrets {
+        eprintln!("!!!   {}", path.display());
+    }
+    eprintln!("!!! Use --block-secrets to leave them out, or --no-warn-secrets to silence this.");
+}
+
+const LOCK_FILE_NAMES: &[&str] = &[
+    "package-lock.json", "npm-shrinkwrap.json", "yarn.lock", "pnpm-lock.yaml", "bun.lockb",
+    "Cargo.lock", "Gemfile.lock", "poetry.lock", "Pipfile.lock", "composer.lock", "go.sum",
+    "packages.lock.json", "flake.lock",
+];
+
+const GENERATED_FILE_SUFFIXES: &[&str] = &[
+    ".lock", ".min.js", ".min.css", ".map", ".pb.go", "_pb2.py", "_pb2_grpc.py", ".pb.cc", ".pb.h",
+    ".g.dart", ".freezed.dart", ".designer.cs", ".generated.ts",
+];
+
+// Lockfiles, generated protobuf/codegen output and minified assets, which grouped output
+// keeps apart from hand-written sources
+fn is_generated_or_lock_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    LOCK_FILE_NAMES.contains(&name) || GENERATED_FILE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+// Parses `ext,ext=Group` entries from --group-map; later entries override earlier ones
+fn parse_group_map(entries: &[String]) -> Result<HashMap<String, String>> {
+    let mut groups = HashMap::new();
+    for entry in entries {
+        let (extensions, group) = entry.rsplit_once('=')
+            .filter(|(extensions, group)| !extensions.trim().is_empty() && !group.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid --group-map '{}' (expected EXTS=GROUP, e.g. 'proto,thrift=Schemas')", entry))?;
+        for ext in extensions.split(',').map(|ext| ext.trim().trim_start_matches('.')).filter(|ext| !ext.is_empty()) {
+            groups.insert(ext.to_lowercase(), group.trim().to_string());
+        }
+    }
+    Ok(groups)
+}
+
+fn group_files_by_type<'a>(files: &'a [(PathBuf, String)], state: &RunState) -> Vec<(String, Vec<&'a (PathBuf, String)>)> {
+    let mut groups: HashMap<String, Vec<&(PathBuf, String)>> = HashMap::new();
+    
+    for file in files {
+        let ext = file.0.extension()
+            .and_then(|e| e.to_str())
+            .or_else(|| shebang_ext(&file.1))
+            .unwrap_or("no-extension");
+        
+        let group = if is_generated_or_lock_file(&file.0) {
+            "Generated/Lock Files"
+        } else if let Some(group) = state.custom_groups.get(&ext.to_lowercase()) {
+            group
+        } else {
+            match ext {
+                "rs" => "Rust Source",
+                "py" => "Python Source", 
+                "js" | "jsx" => "JavaScript Source",
+                "ts" | "tsx" => "TypeScript Source",
+                "html" | "htm" => "HTML Templates",
+                "css" | "scss" | "sass" => "Stylesheets",
+                "json" => "JSON Configuration",
+                "toml" => "TOML Configuration",
+                "yml" | "yaml" => "YAML Configuration",
+                "md" | "markdown" => "Documentation",
+                "txt" | "text" => "Text Files",
+                "sh" | "bash" | "zsh" => "Shell Scripts",
+                "sql" => "Database Scripts",
+                "go" => "Go Source",
+                "java" => "Java Source",
+                "c" | "h" => "C Source",
+                "cpp" | "hpp" | "cc" => "C++ Source",
+                "no-extension" => "Files without extension",
+                _ => "Other Files",
+            }
+        }.to_string();
+        
+        groups.entry(group).or_default().push(file);
+    }
+    
+    let mut sorted_groups: Vec<_> = groups.into_iter().collect();
+    sorted_groups.sort_by_key(|(group_name, files)| (group_name.clone(), files.len()));
+    sorted_groups.reverse();
+    
+    sorted_groups
+}
+
+const FCLIPIGNORE_FILENAME: &str = ".fclipignore";
+const DOCKERIGNORE_FILENAME: &str = ".dockerignore";
+
+const CONFIG_FILENAME: &str = "fclip.toml";
+
+const AFTER_HELP: &str = "\
+EXAMPLES:
+  # Copy all files from the current directory, respecting .gitignore
+  fclip
+
+  # Copy only Rust files from the 'src' directory with structure
+  fclip --include rs --include-structure ./src
+
+  # Copy all markdown files except NOTE.md, limit to 50k tokens
+  fclip --include md --exclude NOTE.md --max-tokens 50000 .
+
+  # Copy all files except log files and temporary files
+  fclip --exclude log --exclude \"*.tmp\" --exclude \"temp*\" .
+
+  # Output to file instead of clipboard, with dependencies info
+  fclip --output-file codebase.txt --include-dependencies .
+
+  # Compress whitespace and group by file type
+  fclip --compress --group-by-type --max-tokens 100000 .
+";
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "A CLI tool to copy the contents of a codebase to the clipboard.",
+    long_about = "fclip walks the specified directory, collects the content of all relevant files, and copies it to the clipboard, formatted with file paths as headers. It intelligently respects .gitignore rules and allows for fine-grained filtering by file extension or filename.",
+    after_help = AFTER_HELP
+)]
+pub struct Config {
+    #[arg(default_value = ".", help = "Files or directories to copy; append :START-END to a file to copy only those lines, or :DEPTH to a directory to override --depth for it")]
+    pub paths: Vec<PathBuf>,
+
+    #[arg(long, short)]
+    pub depth: Option<usize>,
+
+    #[arg(long, help = "Only copy the contents of files at most N directories deep; deeper files still appear in --include-structure")]
+    pub content_depth: Option<usize>,
+
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub use_gitignore: bool,
+
+    #[arg(long, help = "Include hidden files and directories (dotfiles); .git, .hg and .svn are still skipped")]
+    pub hidden: bool,
+
+    #[arg(long, help = "Follow symbolic links while walking; symlink cycles are detected and skipped")]
+    pub follow_symlinks: bool,
+
+    #[arg(long, help = "Don't apply ignore files from directories above the walked paths")]
+    pub no_parent_ignore: bool,
+
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Honor .fclipignore files (gitignore syntax, discovered per directory). --unignore patterns take precedence over them")]
+    pub use_fclipignore: bool,
+
+    #[arg(long = "ignore-file", help = "Also honor ignore files with this name (gitignore syntax, discovered per directory), on top of .dockerignore; repeatable")]
+    pub ignore_file: Vec<String>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub unignore: Option<Vec<String>>,
+
+    #[arg(short, long, value_delimiter = ',', help = "Include files by extension (e.g., 'rs', 'py') or filename (e.g., 'README.md', '*.txt')")]
+    pub include: Option<Vec<String>>,
+
+    #[arg(short, long, value_delimiter = ',', help = "Exclude files by extension (e.g., 'log', 'tmp') or filename (e.g., 'NOTE.md', '*.cache')")]
+    pub exclude: Option<Vec<String>>,
+
+    #[arg(long, value_delimiter = ',', help = "Only include files of these built-in file types (e.g., 'rust', 'py', 'web'); see --list-types")]
+    pub include_type: Option<Vec<String>>,
+
+    #[arg(long, value_delimiter = ',', help = "Exclude files of these built-in file types (e.g., 'json', 'markdown'); see --list-types")]
+    pub exclude_type: Option<Vec<String>>,
+
+    #[arg(long, help = "List the file types accepted by --include-type and --exclude-type, then exit")]
+    pub list_types: bool,
+
+    #[arg(long, value_name = "DIR", help = "Check the --split-by-size chunks in DIR against their .sha256sums files, then exit")]
+    pub verify_chunks: Option<PathBuf>,
+
+    #[arg(long, value_delimiter = ',', help = "Only include files whose path relative to the walked directory matches a glob (e.g., 'src/**/*.rs')")]
+    pub include_glob: Option<Vec<String>>,
+
+    #[arg(long, value_delimiter = ',', help = "Exclude files whose path relative to the walked directory matches a glob (e.g., '**/tests/**')")]
+    pub exclude_glob: Option<Vec<String>>,
+
+    #[arg(long, short)]
+    pub verbose: bool,
+
+    #[arg(long, short, conflicts_with = "verbose", help = "Print nothing but errors and the reports you ask for (--dry-run, --stats, --report-excluded, ...)")]
+    pub quiet: bool,
+
+    #[arg(long, value_enum, default_value_t = ProgressMode::Auto, help = "When to show the reading progress bar; 'auto' shows it only on a terminal unless FCLIP_NO_PROGRESS is set")]
+    pub progress: ProgressMode,
+
+    #[arg(long, help = "indicatif template for the bytes progress bar, e.g. '{bar:40} {percent}%'")]
+    pub progress_template: Option<String>,
+    
+    #[arg(long)]
+    pub dry_run: bool,
+
+    #[arg(long, default_value_t = 10, help = "Deprecated: use --max-size; total size limit in whole megabytes")]
+    pub max_size_mb: usize,
+
+    #[arg(long, help = "Stop adding files once the copied content reaches this total size (e.g., '800kb', '1.5gb'); overrides --max-size-mb [default: 10mb]")]
+    pub max_size: Option<String>,
+
+    #[arg(long, help = "Process at most this many files (in sorted order), omitting the rest")]
+    pub max_files: Option<usize>,
+
+    #[arg(long, help = "Skip individual files smaller than this size (e.g., '500b', '2kb')")]
+    pub min_file_size: Option<String>,
+
+    #[arg(long, help = "Skip individual files larger than this size (e.g., '200kb', '1mb')")]
+    pub max_file_size: Option<String>,
+
+    #[arg(long, help = "Only include files modified within this duration (e.g., '30m', '24h', '7d')")]
+    pub modified_within: Option<String>,
+
+    #[arg(long, value_name = "REF", help = "Only include files changed since this git revision (plus untracked files), labelling each with its git status")]
+    pub since: Option<String>,
+
+    #[arg(long, requires = "since", help = "Add each file's unified diff against the --since revision after its content")]
+    pub with_diff: bool,
+
+    #[arg(long)]
+    pub max_tokens: Option<usize>,
+
+    #[arg(long, value_enum, default_value_t = SortMode::Path, help = "File order: path, size (largest first), tokens (most first), mtime (newest first), or manual (paths in command-line order)")]
+    pub sort: SortMode,
+
+    #[arg(long, value_enum, default_value_t = PathStyle::Relative, help = "How file paths are shown in headers, the structure tree and JSON: relative (to the current directory), absolute, or name-only")]
+    pub path_style: PathStyle,
+
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf, help = "Line endings of the output: lf, crlf, or preserve (keep each file's own endings)")]
+    pub line_ending: LineEnding,
+
+    #[arg(long, conflicts_with = "line_ending", help = "Keep each UTF-8 file byte-for-byte: no BOM stripping and no CRLF to LF conversion")]
+    pub preserve_bytes: bool,
+
+    #[arg(long, value_delimiter = ',', help = "Put files whose relative path matches these globs first (e.g. 'README.md,Cargo.toml'), whatever the --sort order")]
+    pub pin: Option<Vec<String>>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Default, help = "Output format; 'raw' concatenates file contents with no headers or fences")]
+    pub format: OutputFormat,
+
+    #[arg(long, help = "Write --format json on a single line without indentation, saving tokens")]
+    pub json_compact: bool,
+
+    #[arg(long, help = "Nest --format json files into a directory tree object instead of the flat files array")]
+    pub json_tree: bool,
+
+    #[arg(long)]
+    pub stats: bool,
+
+    #[arg(long, help = "After selection, show how the token budget was spent per section and file, and what was cut")]
+    pub budget_report: bool,
+
+    #[arg(long, help = "Estimate the input cost of the copied tokens for a model (e.g., 'gpt-4o', 'claude-3.5-sonnet')")]
+    pub cost_model: Option<String>,
+
+    #[arg(long, help = "Custom price in dollars per million input tokens, overriding --cost-model's built-in rate")]
+    pub cost_per_mtok: Option<f64>,
+
+    #[arg(long, help = "Warn when the output won't fit in this model's context window (e.g., 'gpt-4o', 'claude-3.5-sonnet')")]
+    pub model: Option<String>,
+
+    #[arg(long, requires = "model", help = "Exit with an error instead of warning when the output exceeds --model's context window")]
+    pub fail_if_over_context: bool,
+
+    #[arg(long, help = "Print a hash of the selected files' paths and contents, unaffected by formatting options (also added to JSON output)")]
+    pub fingerprint: bool,
+
+    #[arg(long, default_value_t = 10, help = "Number of largest files by tokens to list in --stats output (0 disables)")]
+    pub top: usize,
+
+    #[arg(long, default_value_t = 1, help = "Directory levels below each path to group by in --stats output (0 groups by input path)")]
+    pub stats_depth: usize,
+
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text, help = "Format of --stats output; 'json' is written to stdout or --stats-file")]
+    pub stats_format: StatsFormat,
+
+    #[arg(long, help = "Write JSON statistics to this file instead of stdout")]
+    pub stats_file: Option<PathBuf>,
+
+    #[arg(long)]
+    pub include_structure: bool,
+
+    #[arg(long, help = "Output only the directory tree of matching files, without reading their contents")]
+    pub tree_only: bool,
+
+    #[arg(long, help = "Annotate the structure tree with file sizes and token estimates, rolled up per directory")]
+    pub tree_sizes: bool,
+
+    #[arg(long, default_value_t = 50, help = "List at most this many entries per directory in the structure tree (0 = unlimited)")]
+    pub tree_max_entries: usize,
+
+    #[arg(long, help = "Draw the structure tree with plain ASCII instead of Unicode box-drawing characters")]
+    pub ascii_tree: bool,
+
+    #[arg(long)]
+    pub include_dependencies: bool,
+
+    #[arg(long, value_name = "ENTRY", help = "Instead of walking the paths, copy ENTRY and the local files it transitively imports (Rust mod/use, Python, JS/TS, C/C++ #include); repeatable")]
+    pub follow_imports: Vec<PathBuf>,
+
+    #[arg(long, requires = "follow_imports", help = "Follow --follow-imports at most this many imports away from the entry files")]
+    pub import_depth: Option<usize>,
+
+    #[arg(long)]
+    pub group_by_type: bool,
+
+    #[arg(long, value_name = "EXTS=GROUP", help = "Put extensions into a --group-by-type group of your own, overriding the built-in one (e.g. 'proto,thrift=Schemas'); repeatable")]
+    pub group_map: Vec<String>,
+
+    #[arg(long)]
+    pub auto_exclude_common: bool,
+
+    #[arg(long, value_delimiter = ',', help = "Add entries to the auto-exclude list (directory/file names or '*.ext')")]
+    pub auto_exclude_add: Option<Vec<String>>,
+
+    #[arg(long, value_delimiter = ',', help = "Remove entries from the auto-exclude list")]
+    pub auto_exclude_remove: Option<Vec<String>>,
+
+    #[arg(long, value_delimiter = ',', help = "Replace the built-in auto-exclude list entirely")]
+    pub auto_exclude_list: Option<Vec<String>>,
+
+    #[arg(long)]
+    pub exclude_empty: bool,
+
+    #[arg(long)]
+    pub compress: bool,
+
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=3), help = "How hard to compress: 0 off, 1 trailing whitespace only, 2 collapse spaces and blank lines (--compress), 3 also drop blank lines")]
+    pub compress_level: Option<u8>,
+
+    #[arg(long, help = "Compress Makefiles and other files where tabs and spacing are significant too")]
+    pub force_compress: bool,
+
+    #[arg(long, help = "Emit identical file contents once and reference the first copy for the rest")]
+    pub dedup: bool,
+
+    #[arg(long, help = "List files skipped by --max-file-size, --max-size or --max-tokens as one-line '(omitted, SIZE)' stubs instead of dropping them")]
+    pub reference_oversized: bool,
+
+    #[arg(long, value_name = "N", help = "Replace any single file over about N tokens with a one-line '(omitted, ~N tokens)' stub, whatever the overall budget")]
+    pub auto_skip_large_tokens: Option<usize>,
+
+    #[arg(long, help = "Drop every empty or whitespace-only line (also inside multi-line strings)")]
+    pub remove_blank_lines: bool,
+
+    #[arg(long, help = "Strip trailing whitespace from every line, leaving indentation and markdown hard line breaks alone")]
+    pub trim_trailing: bool,
+
+    #[arg(long, help = "List every included file with its token estimate before the file contents (default and markdown formats)")]
+    pub toc: bool,
+
+    #[arg(long, conflicts_with_all = ["format", "header_template"], help = "Produce one self-describing document: a summary header, the directory tree, then every file under a standard separator")]
+    pub packed: bool,
+
+    #[arg(long, help = "Text to place before everything else in the output, e.g. instructions for an LLM")]
+    pub prompt: Option<String>,
+
+    #[arg(long, conflicts_with = "prompt", help = "Read the text to place before the output from this file")]
+    pub prompt_file: Option<PathBuf>,
+
+    #[arg(long, help = "Header for each file in the default format; placeholders: {path} {ext} {lines} {tokens} {size} (use {{ and }} for literal braces)")]
+    pub header_template: Option<String>,
+
+    #[arg(long, help = "Line after each file's contents in the default format; same placeholders as --header-template, and \\n, \\t escapes")]
+    pub file_footer: Option<String>,
+
+    #[arg(long, help = "Text after each file in the default format instead of a blank line (e.g. '\\n===EOF===\\n'); same placeholders and escapes as --file-footer")]
+    pub separator: Option<String>,
+
+    #[arg(long, help = "Prefix each line of file contents with its line number")]
+    pub line_numbers: bool,
+
+    #[arg(long, help = "Add each file's modification time and Unix permissions to its header and JSON entry")]
+    pub with_metadata: bool,
+
+    #[arg(long, help = "Include binary files (images, .wasm, ...) as base64 instead of skipping them")]
+    pub include_binary: bool,
+
+    #[arg(long, default_value = "100kb", help = "Largest binary file to include with --include-binary (e.g. 50kb, 1mb)")]
+    pub binary_max_size: String,
+
+    #[arg(long, default_value_t = 30.0, help = "Percentage of control bytes in a file's first 1KB above which it is treated as binary")]
+    pub binary_threshold: f32,
+
+    #[arg(long, help = "Treat files whose NUL bytes follow a UTF-16 pattern as UTF-16 text rather than binary")]
+    pub binary_null_tolerant: bool,
+
+    #[arg(long, help = "Decode files with this encoding (e.g. latin1, utf-16le, shift_jis) instead of auto-detecting non-UTF-8 files")]
+    pub encoding: Option<String>,
+
+    #[arg(long, help = "Keep only the first N lines of longer files, noting how many were cut")]
+    pub truncate_lines: Option<usize>,
+
+    #[arg(long, help = "Cut files at a line boundary once they reach about N estimated tokens")]
+    pub truncate_tokens: Option<usize>,
+
+    #[arg(long, conflicts_with = "truncate_tokens", help = "Cap each file at about N estimated tokens, keeping its beginning and end and cutting from the middle")]
+    pub per_file_tokens: Option<usize>,
+
+    #[arg(long, help = "Only include files whose content matches this regex")]
+    pub grep: Option<String>,
+
+    #[arg(long, requires = "grep", help = "Output only the --grep matches plus N lines of context around each")]
+    pub grep_context: Option<usize>,
+
+    #[arg(long, help = "Skip files whose content matches this regex (e.g., '@generated|DO NOT EDIT')")]
+    pub exclude_matching: Option<String>,
+
+    #[arg(long, help = "Replace likely secrets (API keys, tokens, private keys) with ***REDACTED***")]
+    pub redact: bool,
+
+    #[arg(long, requires = "redact", help = "Additional regex to redact; if it has a capture group only the first group is replaced")]
+    pub redact_pattern: Vec<String>,
+
+    #[arg(long, help = "Don't warn about files whose names suggest secrets (.env, *.pem, id_rsa, ...)")]
+    pub no_warn_secrets: bool,
+
+    #[arg(long, help = "Leave out files whose names suggest secrets (.env, *.pem, *.key, id_rsa, credentials.json, .npmrc, ...)")]
+    pub block_secrets: bool,
+
+    #[arg(long, conflicts_with = "compress_output", help = "Gzip the --output-file (and each --split-by-size chunk), adding .gz to the name; same as --compress-output gzip")]
+    pub gzip: bool,
+
+    #[arg(long, value_enum, default_value_t = OutputCompression::None, help = "Compress the --output-file (and each --split-by-size chunk) with gzip or zstd, adding .gz or .zst to the name")]
+    pub compress_output: OutputCompression,
+
+    #[arg(long, requires = "compress_output", help = "Level for --compress-output: gzip 0-9 (default 6), zstd 1-19 (default 3)")]
+    pub compress_output_level: Option<u32>,
+
+    #[arg(long, help = "Also write NAME.manifest.json next to the --output-file, listing each included file's path, size, tokens and hash")]
+    pub manifest: bool,
+
+    #[arg(long, help = "Write output to this file instead of the clipboard ('-' writes to stdout)")]
+    pub output_file: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "output_file", help = "Write output to stdout instead of the clipboard")]
+    pub stdout: bool,
+
+    #[arg(long, value_name = "FORMAT:PATH", help = "Also write the same files in another format to PATH (e.g. 'json:out.json'), alongside the main output; repeatable")]
+    pub emit: Vec<String>,
+
+    #[arg(long, help = "Append to --output-file instead of overwriting it; with --split-by-size, chunks continue the existing part numbering")]
+    pub append_to_file: bool,
+
+    #[arg(long)]
+    pub split_by_size: Option<String>,
+
+    #[arg(long, help = "Load defaults from this config file instead of searching for fclip.toml")]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, help = "Copy via the OSC 52 terminal escape sequence instead of the system clipboard (default when SSH_TTY is set)")]
+    pub osc52: bool,
+
+    #[arg(long, help = "Fail instead of falling back to stdout when the clipboard is unavailable")]
+    pub clipboard_required: bool,
+
+    #[arg(long, help = "After copying, list every skipped file grouped by the reason it was left out; finding the files hidden by ignore rules costs one more unfiltered walk of each path")]
+    pub report_excluded: bool,
+
+    #[arg(long, help = "Report the size and estimated tokens of the current clipboard text, then exit")]
+    pub show_clipboard: bool,
+
+    #[arg(long, help = "Pick the files to copy from a checklist in the terminal before processing")]
+    pub interactive: bool,
+
+    #[arg(long, help = "After copying, keep watching the paths and copy again whenever a file changes")]
+    pub watch: bool,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathStyle {
+    Relative,
+    Absolute,
+    NameOnly,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Preserve,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortMode {
+    Path,
+    Size,
+    Tokens,
+    Mtime,
+    Manual,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Default,
+    Markdown,
+    Json,
+    Ndjson,
+    Raw,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct FileConfig {
+    paths: Option<Vec<PathBuf>>,
+    depth: Option<usize>,
+    content_depth: Option<usize>,
+    use_gitignore: Option<bool>,
+    hidden: Option<bool>,
+    follow_symlinks: Option<bool>,
+    no_parent_ignore: Option<bool>,
+    use_fclipignore: Option<bool>,
+    ignore_file: Option<Vec<String>>,
+    unignore: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    include_glob: Option<Vec<String>>,
+    include_type: Option<Vec<String>>,
+    exclude_type: Option<Vec<String>>,
+    exclude_glob: Option<Vec<String>>,
+    verbose: Option<bool>,
+    quiet: Option<bool>,
+    progress: Option<ProgressMode>,
+    progress_template: Option<String>,
+    dry_run: Option<bool>,
+    max_size_mb: Option<usize>,
+    max_size: Option<String>,
+    max_files: Option<usize>,
+    min_file_size: Option<String>,
+    max_file_size: Option<String>,
+    modified_within: Option<String>,
+    since: Option<String>,
+    with_diff: Option<bool>,
+    max_tokens: Option<usize>,
+    sort: Option<SortMode>,
+    pin: Option<Vec<String>>,
+    path_style: Option<PathStyle>,
+    line_ending: Option<LineEnding>,
+    preserve_bytes: Option<bool>,
+    format: Option<OutputFormat>,
+    json_compact: Option<bool>,
+    json_tree: Option<bool>,
+    stats: Option<bool>,
+    top: Option<usize>,
+    stats_depth: Option<usize>,
+    budget_report: Option<bool>,
+    cost_model: Option<String>,
+    cost_per_mtok: Option<f64>,
+    model: Option<String>,
+    fail_if_over_context: Option<bool>,
+    fingerprint: Option<bool>,
+    stats_format: Option<StatsFormat>,
+    stats_file: Option<PathBuf>,
+    include_structure: Option<bool>,
+    tree_only: Option<bool>,
+    tree_sizes: Option<bool>,
+    tree_max_entries: Option<usize>,
+    ascii_tree: Option<bool>,
+    include_dependencies: Option<bool>,
+    follow_imports: Option<Vec<PathBuf>>,
+    import_depth: Option<usize>,
+    group_by_type: Option<bool>,
+    group_map: Option<Vec<String>>,
+    // Group name -> extensions, merged into --group-map
+    groups: Option<HashMap<String, Vec<String>>>,
+    auto_exclude_common: Option<bool>,
+    auto_exclude_add: Option<Vec<String>>,
+    auto_exclude_remove: Option<Vec<String>>,
+    auto_exclude_list: Option<Vec<String>>,
+    exclude_empty: Option<bool>,
+    compress: Option<bool>,
+    compress_level: Option<u8>,
+    force_compress: Option<bool>,
+    dedup: Option<bool>,
+    reference_oversized: Option<bool>,
+    auto_skip_large_tokens: Option<usize>,
+    remove_blank_lines: Option<bool>,
+    trim_trailing: Option<bool>,
+    toc: Option<bool>,
+    packed: Option<bool>,
+    prompt: Option<String>,
+    prompt_file: Option<PathBuf>,
+    header_template: Option<String>,
+    file_footer: Option<String>,
+    separator: Option<String>,
+    line_numbers: Option<bool>,
+    with_metadata: Option<bool>,
+    include_binary: Option<bool>,
+    binary_max_size: Option<String>,
+    binary_threshold: Option<f32>,
+    binary_null_tolerant: Option<bool>,
+    encoding: Option<String>,
+    truncate_lines: Option<usize>,
+    truncate_tokens: Option<usize>,
+    per_file_tokens: Option<usize>,
+    grep: Option<String>,
+    grep_context: Option<usize>,
+    exclude_matching: Option<String>,
+    redact: Option<bool>,
+    redact_pattern: Option<Vec<String>>,
+    no_warn_secrets: Option<bool>,
+    block_secrets: Option<bool>,
+    output_file: Option<PathBuf>,
+    stdout: Option<bool>,
+    emit: Option<Vec<String>>,
+    append_to_file: Option<bool>,
+    split_by_size: Option<String>,
+    gzip: Option<bool>,
+    compress_output: Option<OutputCompression>,
+    compress_output_level: Option<u32>,
+    manifest: Option<bool>,
+    osc52: Option<bool>,
+    clipboard_required: Option<bool>,
+    report_excluded: Option<bool>,
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read config file {}: {}", path.display(), e))?;
+        toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    fn apply(mut self, cli: &mut Config, matches: &ArgMatches) {
+        let groups = self.groups.take();
+
+        macro_rules! merge {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if let Some(value) = self.$field {
+                        if matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine) {
+                            cli.$field = value.into();
+                        }
+                    }
+                )*
+            };
+        }
+
+        merge!(
+            paths,
+            depth,
+            content_depth,
+            use_gitignore,
+            hidden,
+            follow_symlinks,
+            no_parent_ignore,
+            use_fclipignore,
+            ignore_file,
+            unignore,
+            include,
+            exclude,
+            include_glob,
+            include_type,
+            exclude_type,
+            exclude_glob,
+            verbose,
+            quiet,
+            progress,
+            progress_template,
+            dry_run,
+            max_size_mb,
+            max_size,
+            max_files,
+            min_file_size,
+            max_file_size,
+            modified_within,
+            since,
+            with_diff,
+            max_tokens,
+            sort,
+            pin,
+            path_style,
+            line_ending,
+            preserve_bytes,
+            format,
+            json_compact,
+            json_tree,
+            stats,
+            top,
+            stats_depth,
+            budget_report,
+            cost_model,
+            cost_per_mtok,
+            model,
+            fail_if_over_context,
+            fingerprint,
+            stats_format,
+            stats_file,
+            include_structure,
+            tree_only,
+            tree_sizes,
+            tree_max_entries,
+            ascii_tree,
+            include_dependencies,
+            follow_imports,
+            import_depth,
+            group_by_type,
+            group_map,
+            auto_exclude_common,
+            auto_exclude_add,
+            auto_exclude_remove,
+            auto_exclude_list,
+            exclude_empty,
+            compress,
+            compress_level,
+            force_compress,
+            dedup,
+            reference_oversized,
+            auto_skip_large_tokens,
+            remove_blank_lines,
+            trim_trailing,
+            toc,
+            packed,
+            prompt,
+            prompt_file,
+            header_template,
+            file_footer,
+            separator,
+            line_numbers,
+            with_metadata,
+            include_binary,
+            binary_max_size,
+            binary_threshold,
+            binary_null_tolerant,
+            encoding,
+            truncate_lines,
+            truncate_tokens,
+            per_file_tokens,
+            grep,
+            grep_context,
+            exclude_matching,
+            redact,
+            redact_pattern,
+            no_warn_secrets,
+            block_secrets,
+            output_file,
+            stdout,
+            emit,
+            append_to_file,
+            gzip,
+            compress_output,
+            compress_output_level,
+            manifest,
+            osc52,
+            clipboard_required,
+            report_excluded,
+            split_by_size,
+        );
+
+        // [groups] goes before --group-map entries, so the command line wins per extension
+        if let Some(groups) = groups {
+            let mut entries: Vec<String> = groups.into_iter()
+                .map(|(group, extensions)| format!("{}={}", extensions.join(","), group))
+                .collect();
+            entries.sort();
+            entries.append(&mut cli.group_map);
+            cli.group_map = entries;
+        }
+    }
+}
+
+fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("fclip").join(CONFIG_FILENAME));
+    }
+
+    paths.push(PathBuf::from(CONFIG_FILENAME));
+    paths
+}
+
+fn load_config(cli: &mut Config, matches: &ArgMatches) -> Result<()> {
+    let config_paths = match &cli.config {
+        Some(path) => vec![path.clone()],
+        None => default_config_paths().into_iter().filter(|p| p.is_file()).collect(),
+    };
+
+    for path in config_paths {
+        FileConfig::load(&path)?.apply(cli, matches);
+        if cli.verbose {
+            eprintln!("Loaded config: {}", path.display());
+        }
+    }
+
+    check_conflicts(cli)
+}
+
+// clap's conflicts_with only sees the command line, so re-check the same pairs once
+// fclip.toml values have been merged in.
+fn check_conflicts(cli: &Config) -> Result<()> {
+    let conflicts = [
+        ("--quiet", "--verbose", cli.quiet && cli.verbose),
+        ("--preserve-bytes", "--line-ending", cli.preserve_bytes && cli.line_ending != LineEnding::Lf),
+        ("--packed", "--format", cli.packed && !matches!(cli.format, OutputFormat::Default)),
+        ("--packed", "--header-template", cli.packed && cli.header_template.is_some()),
+        ("--prompt-file", "--prompt", cli.prompt_file.is_some() && cli.prompt.is_some()),
+        ("--per-file-tokens", "--truncate-tokens", cli.per_file_tokens.is_some() && cli.truncate_tokens.is_some()),
+        ("--gzip", "--compress-output", cli.gzip && cli.compress_output != OutputCompression::None),
+        ("--stdout", "--output-file", cli.stdout && cli.output_file.is_some()),
+    ];
+    for (first, second, conflicting) in conflicts {
+        if conflicting {
+            anyhow::bail!("{} cannot be used with {} (check the command line and fclip.toml)", first, second);
+        }
+    }
+    Ok(())
+}
+
+fn parse_size(size_str: &str) -> Result<usize> {
+    let size_str = size_str.to_lowercase().replace(" ", "");
+    
+    let (num_str, multiplier) = if size_str.ends_with("gb") {
+        (size_str.trim_end_matches("gb"), 1024 * 1024 * 1024)
+    } else if size_str.ends_with("mb") {
+        (size_str.trim_end_matches("mb"), 1024 * 1024)
+    } else if size_str.ends_with("kb") {
+        (size_str.trim_end_matches("kb"), 1024)
+    } else if size_str.ends_with("b") {
+        (size_str.trim_end_matches("b"), 1)
+    } else {
+        (size_str.as_str(), 1)
+    };
+    
+    let num: f64 = num_str.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size format: '{}'", size_str))?;
+    
+    if num < 0.0 {
+        return Err(anyhow::anyhow!("Size cannot be negative"));
+    }
+    
+    let result = (num * multiplier as f64) as usize;
+    
+    if result == 0 && num > 0.0 {
+        Ok(1)
+    } else {
+        Ok(result)
+    }
+}
+
+fn parse_duration(duration_str: &str) -> Result<Duration> {
+    let duration_str = duration_str.to_lowercase().replace(" ", "");
+    
+    let split_at = duration_str
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(duration_str.len());
+    let (num_str, unit) = duration_str.split_at(split_at);
+    
+    let seconds_per_unit = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(anyhow::anyhow!("Invalid duration unit in '{}' (expected s, m, h, d or w)", duration_str)),
+    };
+    
+    let num: f64 = num_str.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration format: '{}'", duration_str))?;
+    
+    Ok(Duration::from_secs_f64(num * seconds_per_unit as f64))
+}
+
+fn is_modified_since(path: &Path, cutoff: SystemTime, verbose: bool) -> bool {
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => modified >= cutoff,
+        Err(e) => {
+            if verbose {
+                eprintln!("Warning: Cannot read modification time of {} ({}), including it anyway", path.display(), e);
+            }
+            true
+        }
+    }
+}
+
+// --compress-output, with --gzip as its older spelling
+fn output_compression(cli: &Config) -> OutputCompression {
+    if cli.gzip { OutputCompression::Gzip } else { cli.compress_output }
+}
+
+// The --output-file as written: `out.txt` becomes `out.txt.gz` or `out.txt.zst` when compressed
+fn compressed_output_path(path: &Path, cli: &Config) -> PathBuf {
+    let extension = match output_compression(cli) {
+        OutputCompression::None => return path.to_path_buf(),
+        OutputCompression::Gzip => "gz",
+        OutputCompression::Zstd => "zst",
+    };
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension)) {
+        path.to_path_buf()
+    } else {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(extension);
+        PathBuf::from(name)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Codec {
+    compression: OutputCompression,
+    level: u32,
+}
+
+impl Codec {
+    fn from_cli(cli: &Config) -> Result<Self> {
+        let compression = output_compression(cli);
+        let (default_level, levels) = match compression {
+            OutputCompression::None => (0, 0..=0),
+            OutputCompression::Gzip => (6, 0..=9),
+            OutputCompression::Zstd => (3, 1..=19),
+        };
+        let level = cli.compress_output_level.unwrap_or(default_level);
+        if !levels.contains(&level) {
+            anyhow::bail!("--compress-output-level for {} must be between {} and {}, got {}",
+                         format!("{:?}", compression).to_lowercase(), levels.start(), levels.end(), level);
+        }
+        Ok(Self { compression, level })
+    }
+    
+    fn label(self) -> Option<&'static str> {
+        match self.compression {
+            OutputCompression::None => None,
+            OutputCompression::Gzip => Some("Gzip"),
+            OutputCompression::Zstd => Some("Zstd"),
+        }
+    }
+}
+
+// A file being written through its --compress-output encoder
+enum FileEncoder {
+    Plain(CountingWriter<fs::File>),
+    Gzip(flate2::write::GzEncoder<CountingWriter<fs::File>>),
+    Zstd(zstd::stream::write::Encoder<'static, CountingWriter<fs::File>>),
+}
+
+impl FileEncoder {
+    fn create(path: &Path, append: bool, codec: Codec) -> Result<Self> {
+        let file = if append {
+            fs::OpenOptions::new().create(true).append(true).open(path)?
+        } else {
+            fs::File::create(path)?
+        };
+        
+        Ok(match codec.compression {
+            OutputCompression::None => FileEncoder::Plain(CountingWriter { inner: file, written: 0 }),
+            OutputCompression::Gzip => FileEncoder::Gzip(flate2::write::GzEncoder::new(
+                CountingWriter { inner: file, written: 0 }, flate2::Compression::new(codec.level))),
+            OutputCompression::Zstd => FileEncoder::Zstd(zstd::stream::write::Encoder::new(
+                CountingWriter { inner: file, written: 0 }, codec.level as i32)?),
+        })
+    }
+    
+    // Completes the file, returning the number of bytes that ended up on disk
+    fn finish(self) -> Result<usize> {
+        match self {
+            FileEncoder::Plain(writer) => Ok(writer.written),
+            FileEncoder::Gzip(encoder) => Ok(encoder.finish()?.written),
+            FileEncoder::Zstd(encoder) => Ok(encoder.finish()?.written),
+        }
+    }
+}
+
+impl Write for FileEncoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            FileEncoder::Plain(writer) => writer.write(buf),
+            FileEncoder::Gzip(encoder) => encoder.write(buf),
+            FileEncoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            FileEncoder::Plain(writer) => writer.flush(),
+            FileEncoder::Gzip(encoder) => encoder.flush(),
+            FileEncoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Returns the number of bytes that ended up on disk. Appending compressed output adds a new
+// gzip member or zstd frame, which both decompress as one continuous stream.
+fn write_file(path: &Path, content: &str, append: bool, codec: Codec) -> Result<usize> {
+    let mut encoder = FileEncoder::create(path, append, codec)?;
+    encoder.write_all(content.as_bytes())?;
+    encoder.finish()
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_output_chunks(content: &str, output_file: &Path, chunk_size: usize, append: bool, codec: Codec, quiet: bool) -> Result<usize> {
+    let base_name = output_file.file_stem().unwrap().to_string_lossy();
+    let extension = output_file.extension().unwrap_or_default().to_string_lossy();
+    let parent = output_file.parent().unwrap_or(Path::new("."));
+    
+    // Output that fits one chunk stays a single file, listed in the sums file all the same
+    // so --verify-chunks can check it
+    if content.len() <= chunk_size {
+        let written = write_file(output_file, content, append, codec)?;
+        if !quiet {
+            println!("Output written to: {}", output_file.display());
+        }
+        let file_name = output_file.file_name().unwrap_or_default().to_string_lossy().to_string();
+        write_chunk_sums(parent, &base_name, &[file_name], append, quiet)?;
+        return Ok(written);
+    }
+    
+    let chunks = split_at_char_boundaries(content, chunk_size);
+    
+    let chunk_filename = |number: usize| if extension.is_empty() {
+        format!("{}_part_{:03}", base_name, number)
+    } else {
+        format!("{}_part_{:03}.{}", base_name, number, extension)
+    };
+    
+    // Appending starts a new series after the parts already on disk, so earlier runs are
+    // never mixed into or overwritten by this one
+    let first_number = if append {
+        last_chunk_number(parent, &base_name, &extension)? + 1
+    } else {
+        1
+    };
+    
+    let mut written = 0;
+    let mut chunk_names = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let chunk_name = chunk_filename(first_number + i);
+        let chunk_path = parent.join(&chunk_name);
+        
+        written += write_file(&chunk_path, chunk, false, codec)?;
+        if !quiet {
+            println!("Chunk {} written to: {}", first_number + i, chunk_path.display());
+        }
+        chunk_names.push(chunk_name);
+    }
+    
+    write_chunk_sums(parent, &base_name, &chunk_names, append, quiet)?;
+    Ok(written)
+}
+
+// Cuts `content` into pieces of at most `chunk_size` bytes without splitting a character; a
+// piece only runs over when a single character is wider than `chunk_size`
+fn split_at_char_boundaries(content: &str, chunk_size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let mut end = chunk_size.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+// Records the SHA-256 of each file in `names` in the sums file for `base_name`. Appending keeps
+// the entries of earlier runs, except those for a file written again.
+fn write_chunk_sums(parent: &Path, base_name: &str, names: &[String], append: bool, quiet: bool) -> Result<()> {
+    let sums_path = parent.join(chunk_sums_filename(base_name));
+    let mut sums = String::new();
+    if append {
+        let existing = fs::read_to_string(&sums_path).unwrap_or_default();
+        for line in existing.lines() {
+            let rewritten = line.split_once("  ").is_some_and(|(_, name)| names.iter().any(|written| written == name));
+            if !rewritten {
+                sums.push_str(line);
+                sums.push('\n');
+            }
+        }
+    }
+    for name in names {
+        // Hashing what landed on disk also catches a write that silently came up short
+        sums.push_str(&format!("{:x}  {}\n", Sha256::digest(fs::read(parent.join(name))?), name));
+    }
+    fs::write(&sums_path, sums)?;
+    if !quiet {
+        println!("Chunk checksums written to: {}", sums_path.display());
+    }
+    Ok(())
+}
+
+// Lists each chunk's SHA-256 in `sha256sum` format, so `sha256sum -c` works on it too
+fn chunk_sums_filename(base_name: &str) -> String {
+    format!("{}.sha256sums", base_name)
+}
+
+/// Checks every chunk listed in the `*.sha256sums` files in `dir` against its recorded hash,
+/// failing if any chunk is missing or has changed.
+pub fn verify_chunks(dir: &Path, quiet: bool) -> Result<()> {
+    let mut sums_files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sha256sums"))
+        .collect();
+    sums_files.sort();
+    if sums_files.is_empty() {
+        anyhow::bail!("No .sha256sums files found in {}", dir.display());
+    }
+    
+    let mut checked = 0;
+    let mut failed = 0;
+    for sums_file in &sums_files {
+        for line in fs::read_to_string(sums_file)?.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((expected, name)) = line.split_once("  ") else {
+                eprintln!("{}: malformed line: {}", sums_file.display(), line);
+                failed += 1;
+                continue;
+            };
+            checked += 1;
+            match fs::read(dir.join(name)) {
+                Ok(bytes) if format!("{:x}", Sha256::digest(&bytes)) == expected => {
+                    if !quiet {
+                        println!("{}: OK", name);
+                    }
+                }
+                Ok(_) => {
+                    println!("{}: FAILED", name);
+                    failed += 1;
+                }
+                Err(e) => {
+                    println!("{}: FAILED ({})", name, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    
+    if failed > 0 {
+        anyhow::bail!("{} of {} chunk(s) failed verification", failed, checked);
+    }
+    if !quiet {
+        eprintln!("Verified {} chunk(s).", checked);
+    }
+    Ok(())
+}
+
+// Highest `<base>_part_NNN[.<ext>]` number in `dir`, or 0 when there are no parts yet
+fn last_chunk_number(dir: &Path, base_name: &str, extension: &str) -> Result<usize> {
+    let prefix = format!("{}_part_", base_name);
+    let suffix = if extension.is_empty() { String::new() } else { format!(".{}", extension) };
+    
+    // A bare file name has an empty parent, which means the current directory
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    
+    let mut last = 0;
+    for entry in entries {
+        let name = entry?.file_name();
+        let number = name.to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<usize>().ok());
+        if let Some(number) = number {
+            last = last.max(number);
+        }
+    }
+    Ok(last)
+}
+
+// The one place that decides which processed files fit --max-size and --max-tokens, so
+// --dry-run lists exactly what a real run copies. Returns the kept files with their total size and tokens.
+fn select_within_budget(processed: Vec<(PathBuf, String)>, cli: &Config, state: &mut RunState, processing: &ProcessingOptions, excluded: &mut ExclusionReport) -> (Vec<(PathBuf, String)>, usize, usize) {
+    let max_size_bytes = processing.max_size;
+    // The prompt is always emitted, so it comes out of the token budget first
+    let prompt_tokens = state.prompt.as_deref().map_or(0, estimate_tokens);
+    let mut files_data = Vec::with_capacity(processed.len());
+    let mut total_size_bytes = 0usize;
+    let mut total_tokens = 0usize;
+    
+    for (file_path, content) in processed {
+        // Stubs for files over --max-file-size cost next to nothing, so they bypass the budgets
+        if state.referenced_files.contains_key(&file_path) {
+            files_data.push((file_path, content));
+            continue;
+        }
+        
+        let content_size = content.len();
+        let content_tokens = file_tokens(&file_path, &content, state);
+        
+        if let Some(limit) = cli.auto_skip_large_tokens.filter(|&limit| content_tokens > limit) {
+            if !cli.quiet {
+                eprintln!("Warning: Skipping {} - ~{} tokens is over --auto-skip-large-tokens {}", 
+                        file_path.display(), content_tokens, limit);
+            }
+            excluded.record(Exclusion::LargeFile, &file_path);
+            state.skipped_large_files.insert(file_path.clone(), content_tokens);
+            reference_file(&file_path, content_size, state, &mut files_data);
+            continue;
+        }
+        
+        if total_size_bytes + content_size > max_size_bytes {
+            if !cli.quiet {
+                eprintln!("Warning: Skipping {} - would exceed size limit of {}", 
+                        file_path.display(), format_size(max_size_bytes));
+            }
+            excluded.record(Exclusion::SizeBudget, &file_path);
+            state.budget_cuts.push((file_path.clone(), content_tokens));
+            if cli.reference_oversized {
+                reference_file(&file_path, content_size, state, &mut files_data);
+            }
+            continue;
+        }
+        
+        if let Some(max_tokens) = cli.max_tokens {
+            if prompt_tokens + total_tokens + content_tokens > max_tokens {
+                if !cli.quiet {
+                    eprintln!("Warning: Skipping {} - would exceed token limit of {}", 
+                            file_path.display(), max_tokens);
+                }
+                excluded.record(Exclusion::TokenBudget, &file_path);
+                state.budget_cuts.push((file_path.clone(), content_tokens));
+                if cli.reference_oversized {
+                    reference_file(&file_path, content_size, state, &mut files_data);
+                }
+                continue;
+            }
+        }
+        
+        total_size_bytes += content_size;
+        total_tokens += content_tokens;
+        if cli.verbose {
+            eprintln!("Added: {} ({} bytes, ~{} tokens)", 
+                    file_path.display(), content_size, content_tokens);
+        }
+        files_data.push((file_path, content));
+    }
+    
+    (files_data, total_size_bytes, total_tokens)
+}
+
+// Adds an empty stub for `path` that the formatters render as `path (omitted, SIZE)`.
+// `fallback_size` is used when the file can no longer be read from disk.
+fn reference_file(path: &Path, fallback_size: usize, state: &mut RunState, files: &mut Vec<(PathBuf, String)>) {
+    let size = fs::metadata(path).map_or(fallback_size, |metadata| metadata.len() as usize);
+    state.referenced_files.insert(path.to_path_buf(), size);
+    files.push((path.to_path_buf(), String::new()));
+}
+
+// Every mode is deterministic: ties fall back to path order, and `manual` keeps the
+// command-line order of the given paths with each directory's files sorted by path
+fn sort_files(files: &mut [(PathBuf, String)], cli: &Config, state: &RunState) {
+    match &cli.sort {
+        SortMode::Path => files.sort_by(|(a, _), (b, _)| a.cmp(b)),
+        SortMode::Size => files.sort_by(|(a_path, a), (b_path, b)| b.len().cmp(&a.len()).then_with(|| a_path.cmp(b_path))),
+        SortMode::Tokens => files.sort_by_cached_key(|(path, content)| (std::cmp::Reverse(file_tokens(path, content, state)), path.clone())),
+        SortMode::Mtime => files.sort_by_cached_key(|(path, _)| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            (std::cmp::Reverse(modified), path.clone())
+        }),
+        SortMode::Manual => {}
+    }
+}
+
+// Moves files matching a --pin glob to the front, in the order the globs were given; the
+// stable sort keeps the --sort order within each pinned group and among unpinned files
+fn pin_files(files: &mut [(PathBuf, String)], pins: &[Pattern], roots: &[PathBuf]) {
+    if pins.is_empty() {
+        return;
+    }
+    
+    files.sort_by_cached_key(|(path, _)| {
+        let root = roots.iter().find(|root| path.starts_with(root)).map_or(path.as_path(), PathBuf::as_path);
+        let relative = relative_path_str(path, root);
+        pins.iter().position(|pin| pin.matches(&relative)).unwrap_or(pins.len())
+    });
+}
+
+// blake3 over the selected files' root-relative paths (plus any line range) and their bytes on
+// disk, sorted by path. Formatting, compression, redaction, ordering and --path-style don't
+// affect it, so it only changes when the selection or the files themselves change.
+fn content_fingerprint(files: &[(PathBuf, String)], state: &RunState) -> String {
+    let mut entries: Vec<(String, Vec<u8>)> = files.iter()
+        .map(|(path, content)| {
+            let root = state.paths.iter()
+                .find(|root| path.starts_with(root))
+                .map_or(path.as_path(), PathBuf::as_path);
+            let mut key = relative_path_str(path, root);
+            if let Some(range) = state.line_ranges.get(path) {
+                key = format!("{}:{}", key, range);
+            }
+            let bytes = fs::read(path).unwrap_or_else(|_| content.clone().into_bytes());
+            (key, bytes)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    
+    let mut hasher = blake3::Hasher::new();
+    for (key, bytes) in &entries {
+        // Length prefixes keep ("ab", "c") and ("a", "bc") from hashing the same
+        hasher.update(&(key.len() as u64).to_le_bytes());
+        hasher.update(key.as_bytes());
+        hasher.update(&(bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+// `codebase.txt` -> `codebase.manifest.json`, and `codebase.txt.gz` -> `codebase.txt.manifest.json`
+fn manifest_path(output_file: &Path) -> PathBuf {
+    output_file.with_extension("manifest.json")
+}
+
+// Records what went into the dump at `output_file`: every file with its size, tokens and a
+// blake3 hash of its bytes on disk, plus the --fingerprint of the whole selection
+fn write_manifest(files: &[(PathBuf, String)], output_file: &Path, cli: &Config, state: &RunState) -> Result<()> {
+    let entries: Vec<Value> = files.iter()
+        .map(|(path, content)| {
+            if let Some(&size) = state.referenced_files.get(path) {
+                return serde_json::json!({ "path": styled_path(path, cli), "omitted": true, "size": size });
+            }
+            let bytes = fs::read(path).unwrap_or_else(|_| content.clone().into_bytes());
+            let mut entry = serde_json::json!({
+                "path": styled_path(path, cli),
+                "size": bytes.len(),
+                "tokens": file_tokens(path, content, state),
+                "blake3": blake3::hash(&bytes).to_hex().to_string()
+            });
+            if let Some(range) = state.line_ranges.get(path) {
+                entry["line_range"] = serde_json::json!({ "start": range.start, "end": range.end });
+            }
+            entry
+        })
+        .collect();
+    
+    let manifest = serde_json::json!({
+        "output": output_file.to_string_lossy(),
+        "created": SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        "fingerprint": state.content_fingerprint.clone().unwrap_or_else(|| content_fingerprint(files, state)),
+        "total_files": files.len(),
+        "total_tokens": files.iter().map(|(path, content)| file_tokens(path, content, state)).sum::<usize>(),
+        "files": entries
+    });
+    
+    let path = manifest_path(output_file);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")
+        .map_err(|e| anyhow::anyhow!("Cannot write manifest {}: {}", path.display(), e))?;
+    if !cli.quiet {
+        eprintln!("Manifest written to: {}", path.display());
+    }
+    Ok(())
+}
+
+fn deduplicate_files(files: &mut [(PathBuf, String)], cli: &Config, state: &RunState) -> HashMap<PathBuf, PathBuf> {
+    let mut first_seen: HashMap<[u8; 32], PathBuf> = HashMap::new();
+    let mut duplicates = HashMap::new();
+    
+    for (path, content) in files.iter_mut() {
+        // Omitted-file stubs are all empty and would otherwise count as copies of each other
+        if state.referenced_files.contains_key(path) {
+            continue;
+        }
+        let hash: [u8; 32] = Sha256::digest(content.as_bytes()).into();
+        match first_seen.get(&hash) {
+            Some(original) => {
+                *content = format!("(identical to {})\n", styled_path(original, cli));
+                duplicates.insert(path.clone(), original.clone());
+            }
+            None => {
+                first_seen.insert(hash, path.clone());
+            }
+        }
+    }
+    
+    duplicates
+}
+
+fn add_line_numbers(content: &str, first_line: usize) -> String {
+    let line_count = content.lines().count();
+    let width = (first_line + line_count).saturating_sub(1).to_string().len();
+    
+    let mut numbered = String::with_capacity(content.len() + line_count * (width + 2));
+    for (i, line) in content.lines().enumerate() {
+        if i > 0 {
+            numbered.push('\n');
+        }
+        numbered.push_str(&format!("{:>width$}| {}", first_line + i, line, width = width));
+    }
+    
+    if content.ends_with('\n') {
+        numbered.push('\n');
+    }
+    
+    numbered
+}
+
+/// Version of the `--format json` and `--format ndjson` document shape. Bumped whenever a
+/// field is renamed or removed; new optional fields don't change it.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// The document written by `--format json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutputDoc {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    // Left out with --json-tree, which places the files in `tree` instead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<DirNode>,
+    pub metadata: Metadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<FileGroup>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<String>,
+}
+
+/// One copied file. Files left out by `--reference-oversized` only carry `path`, `size`
+/// and `omitted`; stubs from `--auto-skip-large-tokens` also keep their `tokens` estimate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileEntry {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub omitted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<usize>,
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_range: Option<LineSpan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numbered_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    // Git status against the --since revision, e.g. "modified" or "renamed from old.rs"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_status: Option<String>,
+    // Unified diff against the --since revision with --with-diff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    // Only set on ndjson lines, where there is no `groups` array to place the file in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    // Last modification time in UTC (RFC 3339) with --with-metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    // Octal permission bits with --with-metadata, on platforms that have them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+/// A directory in `--json-tree` output: its subdirectories and the files directly inside it,
+/// each keyed by name.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DirNode {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dirs: BTreeMap<String, DirNode>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+impl DirNode {
+    // Nests `entry` by `tree_path` rather than by its --path-style path, which may be
+    // absolute or a bare file name
+    fn insert(&mut self, tree_path: &str, entry: FileEntry) {
+        let mut components: Vec<&str> = tree_path.split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .collect();
+        let Some(name) = components.pop().map(str::to_string) else {
+            return;
+        };
+        let mut node = self;
+        for component in components {
+            node = node.dirs.entry(component.to_string()).or_default();
+        }
+        node.files.insert(name, entry);
+    }
+}
+
+// Where --json-tree nests a file: its path below the path argument holding it, under that
+// argument when several were given
+fn json_tree_path(path: &Path, state: &RunState) -> String {
+    let root = state.paths.iter().find(|root| path.starts_with(root));
+    let relative = relative_path_str(path, root.map_or(path, PathBuf::as_path));
+    match root {
+        Some(root) if state.paths.len() > 1 && root != path => {
+            format!("{}/{}", root.to_string_lossy().replace('\\', "/"), relative)
+        }
+        _ => relative,
+    }
+}
+
+/// The lines of a file copied with `file.rs:100-200`; `end` is null for `file.rs:100-`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LineSpan {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileGroup {
+    pub group: String,
+    pub file_count: usize,
+    pub files: Vec<FileEntry>,
+}
+
+/// Totals for the copied files. In ndjson output, which has no enclosing document, the
+/// prompt, fingerprint, structure and dependencies are carried here instead.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Metadata {
+    pub total_files: usize,
+    pub total_size: usize,
+    pub total_tokens: usize,
+    pub grouped: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<String>,
+}
+
+/// The last line of `--format ndjson` output.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NdjsonTrailer {
+    pub schema_version: u32,
+    pub metadata: Metadata,
+}
+
+fn file_json_entry(path: &Path, content: &str, cli: &Config, state: &RunState, duplicate_of: Option<&PathBuf>) -> FileEntry {
+    if let Some(&size) = state.referenced_files.get(path) {
+        return FileEntry {
+            path: styled_path(path, cli),
+            omitted: true,
+            tokens: state.skipped_large_files.get(path).copied(),
+            size,
+            line_range: None,
+            encoding: None,
+            duplicate_of: None,
+            numbered_content: None,
+            content: None,
+            git_status: None,
+            diff: None,
+            group: None,
+            modified: None,
+            mode: None,
+        };
+    }
+    
+    let processed_content = process_content(path, content, cli, state);
+    let is_binary = state.binary_files.contains(path);
+    let change = state.git_changes.get(path);
+    let (modified, mode) = if cli.with_metadata { file_metadata(path) } else { (None, None) };
+    
+    FileEntry {
+        path: styled_path(path, cli),
+        omitted: false,
+        tokens: Some(estimate_tokens(&processed_content)),
+        size: processed_content.len(),
+        line_range: state.line_ranges.get(path).map(|range| LineSpan { start: range.start, end: range.end }),
+        encoding: is_binary.then(|| "base64".to_string()),
+        duplicate_of: duplicate_of.map(|original| styled_path(original, cli)),
+        numbered_content: (cli.line_numbers && !is_binary)
+            .then(|| add_line_numbers(&processed_content, first_line_number(path, state))),
+        content: Some(processed_content),
+        git_status: change.map(|change| change.label()),
+        diff: change.and_then(|change| change.diff.clone()),
+        group: None,
+        modified,
+        mode,
+    }
+}
+
+fn json_metadata(files: &[(PathBuf, String)], cli: &Config) -> Metadata {
+    Metadata {
+        total_files: files.len(),
+        total_size: files.iter().map(|(_, c)| c.len()).sum(),
+        total_tokens: files.iter().map(|(_, c)| estimate_tokens(c)).sum(),
+        grouped: cli.group_by_type,
+        prompt: None,
+        fingerprint: None,
+        structure: None,
+        dependencies: None,
+    }
+}
+
+fn json_dependencies(cli: &Config, state: &RunState) -> Option<String> {
+    if !cli.include_dependencies {
+        return None;
+    }
+    Some(find_dependencies(&state.paths)).filter(|deps| !deps.is_empty())
+}
+
+#[derive(Clone, Copy)]
+enum OutputTarget<'a> {
+    Stdout,
+    File(&'a Path),
+    Clipboard,
+    Osc52,
+}
+
+// Copying nothing would only wipe whatever the user had on the clipboard
+fn would_clear_clipboard(output: &str, target: OutputTarget) -> bool {
+    output.trim().is_empty() && matches!(target, OutputTarget::Clipboard | OutputTarget::Osc52)
+}
+
+// Many terminals drop OSC 52 payloads beyond roughly 100KB of base64
+const OSC52_MAX_ENCODED_BYTES: usize = 100_000;
+
+fn write_osc52(content: &str, quiet: bool) -> Result<()> {
+    use base64::Engine;
+    
+    let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+    if encoded.len() > OSC52_MAX_ENCODED_BYTES && !quiet {
+        eprintln!("Warning: OSC 52 payload is {:.1} KB; many terminals truncate or ignore payloads over {} KB. Consider --output-file instead.",
+                 encoded.len() as f64 / 1024.0, OSC52_MAX_ENCODED_BYTES / 1000);
+    }
+    
+    let sequence = format!("\x1b]52;c;{}\x07", encoded);
+    // tmux only forwards escape sequences to the outer terminal inside a DCS passthrough
+    let sequence = if std::env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    };
+    
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Returns where the output actually went, which differs from `target` when the
+// clipboard is unavailable and the content falls back to stdout
+fn write_output<'a>(content: &str, target: OutputTarget<'a>, cli: &Config) -> Result<OutputTarget<'a>> {
+    match target {
+        OutputTarget::Stdout => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(content.as_bytes())?;
+            stdout.flush()?;
+        }
+        OutputTarget::File(output_file) => {
+            let codec = Codec::from_cli(cli)?;
+            let written = if let Some(split_size_str) = &cli.split_by_size {
+                let split_size = parse_size(split_size_str)?;
+                write_output_chunks(content, output_file, split_size, cli.append_to_file, codec, cli.quiet)?
+            } else {
+                let written = write_file(output_file, content, cli.append_to_file, codec)?;
+                if !cli.quiet {
+                    println!("Output written to: {}", output_file.display());
+                }
+                written
+            };
+            if let Some(label) = codec.label().filter(|_| !cli.quiet) {
+                eprintln!("{}: {:.1} KB -> {:.1} KB", label, content.len() as f64 / 1024.0, written as f64 / 1024.0);
+            }
+        }
+        OutputTarget::Clipboard => {
+            let copied = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(content));
+            if let Err(e) = copied {
+                if cli.clipboard_required {
+                    anyhow::bail!("Clipboard unavailable: {}", e);
+                }
+                if !cli.quiet {
+                    eprintln!("Clipboard unavailable ({}); writing to stdout instead. Use --output-file to save to a file.", e);
+                }
+                return write_output(content, OutputTarget::Stdout, cli);
+            }
+        }
+        OutputTarget::Osc52 => write_osc52(content, cli.quiet)?,
+    }
+    Ok(target)
+}
+
+// Matches files belonging to any of the named built-in types from the ignore crate
+// (`rust`, `py`, `web`, ...). Unknown names are an error rather than matching nothing.
+fn build_type_matcher(names: &Option<Vec<String>>) -> Result<Option<Types>> {
+    let Some(names) = names.as_ref().filter(|names| !names.is_empty()) else {
+        return Ok(None);
+    };
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for name in names {
+        builder.select(name.trim());
+    }
+    let types = builder.build()
+        .map_err(|e| anyhow::anyhow!("{} (run --list-types to see the available types)", e))?;
+    Ok(Some(types))
+}
+
+/// Prints the file types accepted by `--include-type` and `--exclude-type` with their globs.
+pub fn list_types() {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for definition in builder.definitions() {
+        println!("{}: {}", definition.name(), definition.globs().join(", "));
+    }
+}
+
+/// Prints the current clipboard contents with their size and token count.
+pub fn show_clipboard(cli: &Config) {
+    let text = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+    
+    match text {
+        Ok(text) => {
+            let tokens = estimate_tokens(&text);
+            eprintln!("Clipboard: {} lines, {:.1} KB, ~{} tokens", 
+                     text.lines().count(), text.len() as f64 / 1024.0, tokens);
+            if let Some(max_tokens) = cli.max_tokens {
+                if tokens > max_tokens {
+                    eprintln!("Over the --max-tokens budget of {} by ~{} tokens", max_tokens, tokens - max_tokens);
+                } else {
+                    eprintln!("Within the --max-tokens budget of {} (~{} tokens to spare)", max_tokens, max_tokens - tokens);
+                }
+            }
+        }
+        Err(e) => eprintln!("Could not read text from the clipboard: {}", e),
+    }
+}
+
+fn lang_for_ext(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "hs" => "haskell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "json" => "json",
+        "toml" => "toml",
+        "yml" | "yaml" => "yaml",
+        "xml" | "csproj" => "xml",
+        "md" | "markdown" => "markdown",
+        "sh" | "bash" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "proto" => "protobuf",
+        "graphql" | "gql" => "graphql",
+        "tf" => "hcl",
+        "dockerfile" => "dockerfile",
+        _ => "",
+    }
+}
+
+fn lang_for_path(path: &Path, content: &str) -> &'static str {
+    if path.file_name().is_some_and(|name| name.eq_ignore_ascii_case("Dockerfile")) {
+        return "dockerfile";
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => lang_for_ext(ext),
+        None => lang_for_ext(shebang_ext(content).unwrap_or("")),
+    }
+}
+
+// The extension matching a script's `#!` interpreter, for extensionless files such as
+// `bin/deploy`. Handles `#!/usr/bin/env -S python3 -u` and versioned names like `python3.12`.
+fn shebang_ext(content: &str) -> Option<&'static str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    
+    match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" | "pypy" => Some("py"),
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => Some("sh"),
+        "node" | "nodejs" => Some("js"),
+        "deno" | "ts-node" | "tsx" | "bun" => Some("ts"),
+        "ruby" => Some("rb"),
+        "php" => Some("php"),
+        "lua" | "luajit" => Some("lua"),
+        "pwsh" => Some("ps1"),
+        _ => None,
+    }
+}
+
+fn format_output(files: &[(PathBuf, String)], format: &OutputFormat, cli: &Config, state: &RunState) -> String {
+    let mut buffer = Vec::new();
+    write_formatted(files, format, cli, state, &mut OutputSink::new(&mut buffer, cli))
+        .expect("writing to memory cannot fail");
+    String::from_utf8(buffer).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+// Receives formatted output piece by piece, keeping a running token estimate so streamed
+// output can be summarised without ever holding all of it
+struct OutputSink<'w> {
+    out: &'w mut dyn Write,
+    tokens: usize,
+    // Headers and other generated text use LF, so --line-ending crlf is applied here as well
+    crlf: bool,
+}
+
+impl<'w> OutputSink<'w> {
+    fn new(out: &'w mut dyn Write, cli: &Config) -> Self {
+        Self { out, tokens: 0, crlf: output_crlf(cli) }
+    }
+
+    fn emit(&mut self, text: &str) -> std::io::Result<()> {
+        let converted;
+        let text = if self.crlf {
+            converted = to_crlf(text);
+            &converted
+        } else {
+            text
+        };
+        self.tokens += estimate_tokens(text);
+        self.out.write_all(text.as_bytes())
+    }
+}
+
+fn write_formatted(files: &[(PathBuf, String)], format: &OutputFormat, cli: &Config, state: &RunState, sink: &mut OutputSink) -> std::io::Result<()> {
+    let tree_entries = structure_entries(files, cli, state);
+    
+    if matches!(format, OutputFormat::Json) {
+        // Grouped output lists the files in group order, and again under each group
+        let groups: Option<Vec<FileGroup>> = cli.group_by_type.then(|| {
+            group_files_by_type(files, state).into_iter()
+                .map(|(group_name, group_files)| FileGroup {
+                    group: group_name,
+                    file_count: group_files.len(),
+                    files: group_files.iter()
+                        .map(|(path, content)| file_json_entry(path, content, cli, state, state.duplicates.get(path)))
+                        .collect(),
+                })
+                .collect()
+        });
+        let tree = cli.json_tree.then(|| {
+            let mut root = DirNode::default();
+            for (path, content) in files {
+                root.insert(&json_tree_path(path, state), file_json_entry(path, content, cli, state, state.duplicates.get(path)));
+            }
+            root
+        });
+        let files_json: Vec<FileEntry> = match (&tree, &groups) {
+            (Some(_), _) => Vec::new(),
+            (None, Some(groups)) => groups.iter().flat_map(|group| group.files.iter().cloned()).collect(),
+            (None, None) => files.iter()
+                .map(|(path, content)| file_json_entry(path, content, cli, state, state.duplicates.get(path)))
+                .collect(),
+        };
+        
+        let doc = OutputDoc {
+            schema_version: JSON_SCHEMA_VERSION,
+            prompt: state.prompt.clone(),
+            files: files_json,
+            tree,
+            metadata: json_metadata(files, cli),
+            fingerprint: state.content_fingerprint.clone(),
+            groups,
+            structure: include_structure(cli).then(|| generate_directory_tree(&state.paths, &tree_entries, cli)),
+            dependencies: json_dependencies(cli, state),
+        };
+        
+        let json = if cli.json_compact {
+            serde_json::to_string(&doc)
+        } else {
+            serde_json::to_string_pretty(&doc)
+        };
+        let json = json.unwrap_or_else(|_| "Error formatting JSON".to_string());
+        return sink.emit(&json);
+    }
+    
+    if matches!(format, OutputFormat::Ndjson) {
+        return write_ndjson(files, cli, state, &tree_entries, sink);
+    }
+    
+    if cli.packed {
+        sink.emit(&packed_summary(files, state))?;
+    }
+    
+    if let Some(prompt) = &state.prompt {
+        sink.emit(prompt.trim_end())?;
+        sink.emit("\n\n")?;
+    }
+    
+    if include_structure(cli) {
+        sink.emit(&generate_directory_tree(&state.paths, &tree_entries, cli))?;
+    }
+    
+    if cli.include_dependencies {
+        let deps = find_dependencies(&state.paths);
+        if !deps.is_empty() {
+            sink.emit(&deps)?;
+        }
+    }
+    
+    if cli.toc && !matches!(format, OutputFormat::Raw) {
+        sink.emit(&generate_toc(files, format, cli, state))?;
+    }
+    
+    if cli.group_by_type {
+        for (group_name, group_files) in group_files_by_type(files, state) {
+            if !matches!(format, OutputFormat::Raw) {
+                sink.emit(&format!("# {}\n\n", group_name))?;
+            }
+            for (path, content) in group_files {
+                sink.emit(&format_file_block(path, content, format, cli, state))?;
+            }
+            if !matches!(format, OutputFormat::Raw) {
+                sink.emit("\n")?;
+            }
+        }
+    } else {
+        for (path, content) in files {
+            sink.emit(&format_file_block(path, content, format, cli, state))?;
+        }
+    }
+    
+    Ok(())
+}
+
+// One compact JSON object per file and line, followed by a single `{"metadata": ...}` line
+// that also carries the prompt, structure and dependencies when requested
+fn write_ndjson(files: &[(PathBuf, String)], cli: &Config, state: &RunState, tree_entries: &[TreeEntry], sink: &mut OutputSink) -> std::io::Result<()> {
+    if cli.group_by_type {
+        for (group_name, group_files) in group_files_by_type(files, state) {
+            for (path, content) in group_files {
+                let mut entry = file_json_entry(path, content, cli, state, state.duplicates.get(path));
+                entry.group = Some(group_name.clone());
+                sink.emit(&format!("{}\n", serde_json::to_string(&entry).unwrap_or_default()))?;
+            }
+        }
+    } else {
+        for (path, content) in files {
+            let entry = file_json_entry(path, content, cli, state, state.duplicates.get(path));
+            sink.emit(&format!("{}\n", serde_json::to_string(&entry).unwrap_or_default()))?;
+        }
+    }
+    
+    let trailer = NdjsonTrailer {
+        schema_version: JSON_SCHEMA_VERSION,
+        metadata: Metadata {
+            prompt: state.prompt.clone(),
+            fingerprint: state.content_fingerprint.clone(),
+            structure: include_structure(cli).then(|| generate_directory_tree(&state.paths, tree_entries, cli)),
+            dependencies: json_dependencies(cli, state),
+            ..json_metadata(files, cli)
+        },
+    };
+    sink.emit(&format!("{}\n", serde_json::to_string(&trailer).unwrap_or_default()))
+}
+
+// One file's header and body in the default, markdown or raw format
+fn format_file_block(path: &Path, content: &str, format: &OutputFormat, cli: &Config, state: &RunState) -> String {
+    if state.referenced_files.contains_key(path) {
+        return match format {
+            OutputFormat::Markdown => format!("## {}\n\n", display_path(path, cli, state)),
+            OutputFormat::Raw => String::new(),
+            OutputFormat::Default | OutputFormat::Json | OutputFormat::Ndjson => {
+                format!("{}{}{}", render_file_header(path, "", cli, state), render_file_footer(path, "", cli, state), render_separator(path, "", cli, state))
+            }
+        };
+    }
+    
+    let processed_content = process_content(path, content, cli, state);
+    let processed_content = if cli.line_numbers && !matches!(format, OutputFormat::Raw) && !state.binary_files.contains(path) {
+        add_line_numbers(&processed_content, first_line_number(path, state))
+    } else {
+        processed_content
+    };
+    let newline = if processed_content.ends_with('\n') { "" } else { "\n" };
+    let diff = state.git_changes.get(path).and_then(|change| change.diff.as_deref());
+    
+    match format {
+        OutputFormat::Markdown => {
+            let mut block = format!(
+                "## {}\n\n```{}\n{}{}```\n\n",
+                display_path(path, cli, state), lang_for_path(path, content), processed_content, newline
+            );
+            if let Some(diff) = diff {
+                block.push_str(&format!("Changes since {}:\n\n```diff\n{}```\n\n", cli.since.as_deref().unwrap_or_default(), diff));
+            }
+            block
+        }
+        OutputFormat::Raw => format!("{}{}\n", processed_content, newline),
+        OutputFormat::Default | OutputFormat::Json | OutputFormat::Ndjson => {
+            let mut block = format!(
+                "{}{}{}{}{}",
+                render_file_header(path, &processed_content, cli, state), processed_content, newline,
+                render_file_footer(path, &processed_content, cli, state), render_separator(path, &processed_content, cli, state)
+            );
+            if let Some(diff) = diff {
+                block.push_str(&format!("--- changes since {} ---\n{}\n", cli.since.as_deref().unwrap_or_default(), diff));
+            }
+            block
+        }
+    }
+}
+
+// Writes straight to --output-file one file block at a time instead of building the whole
+// output first. Returns the output token estimate.
+fn write_output_streaming(files: &[(PathBuf, String)], cli: &Config, state: &RunState, output_file: &Path) -> Result<usize> {
+    let codec = Codec::from_cli(cli)?;
+    let encoder = FileEncoder::create(output_file, cli.append_to_file, codec)?;
+    let mut writer = CountingWriter { inner: std::io::BufWriter::new(encoder), written: 0 };
+    let mut sink = OutputSink::new(&mut writer, cli);
+    write_formatted(files, &cli.format, cli, state, &mut sink)?;
+    let tokens = sink.tokens;
+    let uncompressed = writer.written;
+    let written = writer.inner.into_inner().map_err(|e| e.into_error())?.finish()?;
+    if let Some(label) = codec.label().filter(|_| !cli.quiet) {
+        eprintln!("{}: {:.1} KB -> {:.1} KB", label, uncompressed as f64 / 1024.0, written as f64 / 1024.0);
+    }
+    
+    if !cli.quiet {
+        println!("Output written to: {}", output_file.display());
+    }
+    Ok(tokens)
+}
+
+fn generate_toc(files: &[(PathBuf, String)], format: &OutputFormat, cli: &Config, state: &RunState) -> String {
+    // List files in the order their bodies are emitted
+    let ordered: Vec<&(PathBuf, String)> = if cli.group_by_type {
+        group_files_by_type(files, state).into_iter().flat_map(|(_, group)| group).collect()
+    } else {
+        files.iter().collect()
+    };
+    
+    let mut toc = String::from("## Table of Contents\n\n");
+    for (i, (path, content)) in ordered.into_iter().enumerate() {
+        let label = display_path(path, cli, state);
+        let tokens = file_tokens(path, content, state);
+        if matches!(format, OutputFormat::Markdown) {
+            toc.push_str(&format!("{}. [{}](#{}) (~{} tokens)\n", i + 1, label, markdown_anchor(&label), tokens));
+        } else {
+            toc.push_str(&format!("{}. {} (~{} tokens)\n", i + 1, label, tokens));
+        }
+    }
+    toc.push('\n');
+    
+    toc
+}
+
+// GitHub-style heading anchor: lowercase, punctuation dropped, spaces become hyphens
+fn markdown_anchor(heading: &str) -> String {
+    heading.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// A wildcard-free --unignore pattern names specific files and overrides auto-exclude;
+// a wildcard pattern such as '*.lock' only overrides ignore files
+fn unignore_names_file(path: &Path, unignore_patterns: &[Pattern]) -> bool {
+    unignore_patterns.iter()
+        .filter(|pattern| !pattern.as_str().contains(['*', '?', '[']))
+        .any(|pattern| should_unignore_file(path, std::slice::from_ref(pattern), false))
+}
+
+fn should_unignore_file(path: &Path, unignore_patterns: &[Pattern], verbose: bool) -> bool {
+    let path_str = path.to_string_lossy();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    
+    for pattern in unignore_patterns {
+        if pattern.matches(&path_str) {
+            if verbose {
+                eprintln!("File {} matches unignore pattern {} (full path)", path_str, pattern);
+            }
+            return true;
+        }
+        
+        if pattern.matches(&file_name) {
+            if verbose {
+                eprintln!("File {} matches unignore pattern {} (filename)", path_str, pattern);
+            }
+            return true;
+        }
+        
+        let unix_path = path_str.replace('\\', "/");
+        if pattern.matches(&unix_path) {
+            if verbose {
+                eprintln!("File {} matches unignore pattern {} (unix path)", path_str, pattern);
+            }
+            return true;
+        }
+    }
+    
+    false
+}
+
+const REDACTED: &str = "***REDACTED***";
+
+enum RedactionTarget {
+    WholeMatch,
+    Group(usize),
+    HighEntropyGroup(usize),
+}
+
+struct RedactionRule {
+    regex: Regex,
+    target: RedactionTarget,
+}
+
+struct Redactor {
+    rules: Vec<RedactionRule>,
+}
+
+impl Redactor {
+    fn new(extra_patterns: &[String]) -> Result<Self> {
+        let builtin = [
+            // PEM private key bodies, keeping the BEGIN/END markers
+            (r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----\s*(.*?)\s*-----END [A-Z ]*PRIVATE KEY-----", RedactionTarget::Group(1)),
+            // AWS access key ids
+            (r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b", RedactionTarget::WholeMatch),
+            // JSON Web Tokens
+            (r"\beyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+", RedactionTarget::WholeMatch),
+            // Environment-style assignments: API_KEY=..., export DB_PASSWORD="..."
+            (r#"(?m)^\s*(?:export\s+)?[A-Z0-9_]*(?:API_?KEY|SECRET|TOKEN|PASSWORD|PASSWD)[A-Z0-9_]*\s*=\s*["']?([^\s"']+)"#, RedactionTarget::Group(1)),
+            // Quoted values for secret-like keys: "client_secret": "...", password = '...'
+            (r#"(?i)[A-Z0-9_.-]*(?:api[_-]?key|secret|token|passw(?:or)?d)[A-Z0-9_.-]*["']?\s*[:=]\s*["']([^"'\s]+)["']"#, RedactionTarget::Group(1)),
+            // Long random-looking string literals assigned to anything
+            (r#"[A-Za-z_][A-Za-z0-9_.-]*["']?\s*[:=]\s*["']([A-Za-z0-9+/=_-]{24,})["']"#, RedactionTarget::HighEntropyGroup(1)),
+        ];
+        
+        let mut rules = Vec::new();
+        for (pattern, target) in builtin {
+            rules.push(RedactionRule {
+                regex: Regex::new(pattern).expect("built-in redaction pattern is valid"),
+                target,
+            });
+        }
+        
+        for pattern in extra_patterns {
+            let regex = Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid redaction pattern '{}': {}", pattern, e))?;
+            let target = if regex.captures_len() > 1 {
+                RedactionTarget::Group(1)
+            } else {
+                RedactionTarget::WholeMatch
+            };
+            rules.push(RedactionRule { regex, target });
+        }
+        
+        Ok(Self { rules })
+    }
+    
+    fn redact(&self, content: &str) -> (String, usize) {
+        let mut result = content.to_string();
+        let mut count = 0;
+        
+        for rule in &self.rules {
+            let mut redacted = String::with_capacity(result.len());
+            let mut last_end = 0;
+            
+            for caps in rule.regex.captures_iter(&result) {
+                let secret = match rule.target {
+                    RedactionTarget::WholeMatch => caps.get(0),
+                    RedactionTarget::Group(i) => caps.get(i),
+                    RedactionTarget::HighEntropyGroup(i) => {
+                        caps.get(i).filter(|m| shannon_entropy(m.as_str()) >= 4.0)
+                    }
+                };
+                
+                if let Some(secret) = secret {
+                    if secret.as_str() == REDACTED || secret.as_str().is_empty() {
+                        continue;
+                    }
+                    redacted.push_str(&result[last_end..secret.start()]);
+                    redacted.push_str(REDACTED);
+                    last_end = secret.end();
+                    count += 1;
+                }
+            }
+            
+            redacted.push_str(&result[last_end..]);
+            result = redacted;
+        }
+        
+        (result, count)
+    }
+}
+
+fn shannon_entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    
+    let len = text.chars().count() as f64;
+    counts.values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LineRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl LineRange {
+    // Accepts `START-END`, `START-` (to end of file) and `START` (a single line); lines are 1-based
+    fn parse(spec: &str) -> Option<Self> {
+        let (start, end) = match spec.split_once('-') {
+            Some((start, "")) => (start.parse().ok()?, None),
+            Some((start, end)) => (start.parse().ok()?, Some(end.parse().ok()?)),
+            None => {
+                let line = spec.parse().ok()?;
+                (line, Some(line))
+            }
+        };
+        Some(Self { start, end })
+    }
+
+    // Out-of-range bounds are clamped to the file, so an oversized END just reads to the last line
+    fn select(&self, content: &str) -> String {
+        let start = self.start.max(1);
+        let end = self.end.unwrap_or(usize::MAX);
+        
+        let mut selected: String = content.lines()
+            .skip(start - 1)
+            .take(end.saturating_sub(start - 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        
+        if !selected.is_empty() {
+            selected.push('\n');
+        }
+        
+        selected
+    }
+}
+
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.end {
+            Some(end) if end == self.start => write!(f, "line {}", self.start),
+            Some(end) => write!(f, "lines {}-{}", self.start, end),
+            None => write!(f, "lines {}-end", self.start),
+        }
+    }
+}
+
+// Splits `file.rs:100-200` style arguments into the file path and its line range. A
+// path that exists as given is never split, so files with colons in their names still work.
+fn split_line_ranges(paths: &[PathBuf]) -> (Vec<PathBuf>, HashMap<PathBuf, LineRange>) {
+    let mut plain_paths = Vec::with_capacity(paths.len());
+    let mut line_ranges = HashMap::new();
+    
+    for path in paths {
+        let path_str = path.to_string_lossy();
+        let split = path_str.rsplit_once(':')
+            .filter(|_| !path.exists())
+            .and_then(|(file, spec)| Some((PathBuf::from(file), LineRange::parse(spec)?)))
+            .filter(|(file, _)| file.is_file());
+        
+        match split {
+            Some((file, range)) => {
+                line_ranges.insert(file.clone(), range);
+                plain_paths.push(file);
+            }
+            None => plain_paths.push(path.clone()),
+        }
+    }
+    
+    (plain_paths, line_ranges)
+}
+
+// Splits `docs:1` style arguments into the directory and the walk depth that replaces
+// --depth for it. As with line ranges, a path that exists as given is never split.
+fn split_path_depths(paths: &[PathBuf]) -> (Vec<PathBuf>, HashMap<PathBuf, usize>) {
+    let mut plain_paths = Vec::with_capacity(paths.len());
+    let mut path_depths = HashMap::new();
+    
+    for path in paths {
+        let path_str = path.to_string_lossy();
+        let split = path_str.rsplit_once(':')
+            .filter(|_| !path.exists())
+            .and_then(|(dir, depth)| Some((PathBuf::from(dir), depth.parse::<usize>().ok()?)))
+            .filter(|(dir, _)| dir.is_dir());
+        
+        match split {
+            Some((dir, depth)) => {
+                path_depths.insert(dir.clone(), depth);
+                plain_paths.push(dir);
+            }
+            None => plain_paths.push(path.clone()),
+        }
+    }
+    
+    (plain_paths, path_depths)
+}
+
+// The walk depth for one of `state.paths`: its `dir:DEPTH` override, else --depth
+fn walk_depth(path: &Path, cli: &Config, state: &RunState) -> Option<usize> {
+    state.path_depths.get(path).copied().or(cli.depth)
+}
+
+fn styled_path(path: &Path, cli: &Config) -> String {
+    let styled = match cli.path_style {
+        PathStyle::Relative => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let relative = path.strip_prefix(&cwd).unwrap_or(path);
+            // components() drops the `./` prefix and any interior `.` segments
+            let relative: PathBuf = relative.components().collect();
+            if relative.as_os_str().is_empty() { PathBuf::from(".") } else { relative }
+        }
+        PathStyle::Absolute => path.canonicalize()
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        PathStyle::NameOnly => path.file_name()
+            .map(PathBuf::from)
+            .or_else(|| path.canonicalize().ok().and_then(|p| p.file_name().map(PathBuf::from)))
+            .unwrap_or_else(|| path.to_path_buf()),
+    };
+    styled.display().to_string()
+}
+
+fn display_path(path: &Path, cli: &Config, state: &RunState) -> String {
+    if let Some(&tokens) = state.skipped_large_files.get(path) {
+        return format!("{} (omitted, ~{} tokens)", styled_path(path, cli), tokens);
+    }
+    if let Some(&size) = state.referenced_files.get(path) {
+        return format!("{} (omitted, {})", styled_path(path, cli), format_size(size));
+    }
+    
+    let mut labels = Vec::new();
+    if state.binary_files.contains(path) {
+        labels.push("binary, base64".to_string());
+    } else {
+        labels.extend(state.line_ranges.get(path).map(|range| range.to_string()));
+        labels.extend(state.git_changes.get(path).map(|change| change.label()));
+    }
+    if cli.with_metadata {
+        let (modified, mode) = file_metadata(path);
+        labels.extend(modified.map(|modified| format!("modified {}", modified)));
+        labels.extend(mode.map(|mode| format!("mode {}", mode)));
+    }
+    
+    if labels.is_empty() {
+        styled_path(path, cli)
+    } else {
+        format!("{} ({})", styled_path(path, cli), labels.join(", "))
+    }
+}
+
+// --with-metadata: the modification time as RFC 3339 UTC and, on Unix, the octal permission bits
+fn file_metadata(path: &Path) -> (Option<String>, Option<String>) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return (None, None);
+    };
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|since_epoch| format_utc_timestamp(since_epoch.as_secs()));
+    
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(format!("{:04o}", metadata.permissions().mode() & 0o7777))
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    
+    (modified, mode)
+}
+
+const DEFAULT_HEADER_TEMPLATE: &str = "--- {path} ---";
+
+const PACKED_HEADER_TEMPLATE: &str = "================ File: {path} ================";
+
+// --packed is a preset over the default format, which it conflicts with: the packed header
+// and the structure section, with the rest of the layout coming from packed_summary
+fn include_structure(cli: &Config) -> bool {
+    cli.include_structure || cli.packed
+}
+
+// The opening block of --packed output. Everything but the timestamp depends only on the
+// files, and SOURCE_DATE_EPOCH pins the timestamp for reproducible documents.
+fn packed_summary(files: &[(PathBuf, String)], state: &RunState) -> String {
+    let generated = std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    let total_tokens: usize = files.iter().map(|(path, content)| file_tokens(path, content, state)).sum();
+    
+    format!(
+        "# Packed by fclip {}\n\n\
+         Generated: {}\n\
+         Files: {}\n\
+         Total tokens: ~{}\n\n\
+         This document packs a selection of files into one text for a language model. It has this \
+         summary, the directory tree of the packed files, then each file in full, introduced by a \
+         '================ File: <path> ================' line. Paths are relative to the packed \
+         directories.\n\n",
+        env!("CARGO_PKG_VERSION"), format_utc_timestamp(generated), files.len(), total_tokens
+    )
+}
+
+// Seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+fn format_utc_timestamp(secs: u64) -> String {
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days conversion, counting from 0000-03-01 so leap days fall at the end of a year
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3_600, time % 3_600 / 60, time % 60)
+}
+
+fn render_file_header(path: &Path, content: &str, cli: &Config, state: &RunState) -> String {
+    let default_template = if cli.packed { PACKED_HEADER_TEMPLATE } else { DEFAULT_HEADER_TEMPLATE };
+    let mut header = expand_placeholders(cli.header_template.as_deref().unwrap_or(default_template), path, content, cli, state);
+    header.push('\n');
+    header
+}
+
+fn render_file_footer(path: &Path, content: &str, cli: &Config, state: &RunState) -> String {
+    match &cli.file_footer {
+        Some(footer) => {
+            let mut footer = expand_placeholders(&unescape(footer), path, content, cli, state);
+            footer.push('\n');
+            footer
+        }
+        None => String::new(),
+    }
+}
+
+// What follows each file: a blank line unless --separator says otherwise
+fn render_separator(path: &Path, content: &str, cli: &Config, state: &RunState) -> String {
+    match &cli.separator {
+        Some(separator) => expand_placeholders(&unescape(separator), path, content, cli, state),
+        None => "\n".to_string(),
+    }
+}
+
+// \n, \r, \t and \\ in --file-footer and --separator, which are hard to type literally
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+// Expands {path}, {ext}, {lines}, {tokens} and {size} in --header-template, --file-footer and
+// --separator. `{{` and `}}` produce literal braces; unknown placeholders are kept verbatim.
+fn expand_placeholders(template: &str, path: &Path, content: &str, cli: &Config, state: &RunState) -> String {
+    let mut expanded = String::with_capacity(template.len() + 32);
+    let mut rest = template;
+    
+    while let Some(pos) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            expanded.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        
+        let placeholder = rest.strip_prefix('{')
+            .and_then(|after| after.find('}').map(|end| &after[..end]));
+        let value = match placeholder {
+            Some("path") => Some(display_path(path, cli, state)),
+            Some("ext") => Some(path.extension().and_then(|e| e.to_str()).unwrap_or("").to_string()),
+            Some("lines") => Some(content.lines().count().to_string()),
+            Some("tokens") => Some(estimate_tokens(content).to_string()),
+            Some("size") => Some(content.len().to_string()),
+            _ => None,
+        };
+        
+        match (placeholder, value) {
+            (Some(name), Some(value)) => {
+                expanded.push_str(&value);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                expanded.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    
+    expanded.push_str(rest);
+    expanded
+}
+
+fn first_line_number(path: &Path, state: &RunState) -> usize {
+    state.line_ranges.get(path).map_or(1, |range| range.start.max(1))
+}
+
+struct ProcessingOptions {
+    // Total budget from --max-size, or the deprecated --max-size-mb
+    max_size: usize,
+    min_file_size: Option<usize>,
+    max_file_size: Option<usize>,
+    redactor: Option<Redactor>,
+    redaction_count: AtomicUsize,
+    trimmed_bytes: AtomicUsize,
+    grep: Option<Regex>,
+    grep_excluded_count: AtomicUsize,
+    exclude_matching: Option<Regex>,
+    matching_excluded_count: AtomicUsize,
+    encoding: Option<&'static encoding_rs::Encoding>,
+    binary_max_size: usize,
+    binary_threshold: f32,
+    binary_files: Mutex<HashSet<PathBuf>>,
+}
+
+impl ProcessingOptions {
+    fn from_cli(cli: &Config) -> Result<Self> {
+        Ok(Self {
+            max_size: match &cli.max_size {
+                Some(size) => parse_size(size)?,
+                None => cli.max_size_mb * 1024 * 1024,
+            },
+            min_file_size: cli.min_file_size.as_deref().map(parse_size).transpose()?,
+            max_file_size: cli.max_file_size.as_deref().map(parse_size).transpose()?,
+            redactor: if cli.redact { Some(Redactor::new(&cli.redact_pattern)?) } else { None },
+            redaction_count: AtomicUsize::new(0),
+            trimmed_bytes: AtomicUsize::new(0),
+            grep: cli.grep.as_deref()
+                .map(|pattern| regex::RegexBuilder::new(pattern).multi_line(true).build().map_err(|e| anyhow::anyhow!("Invalid --grep pattern '{}': {}", pattern, e)))
+                .transpose()?,
+            grep_excluded_count: AtomicUsize::new(0),
+            exclude_matching: cli.exclude_matching.as_deref()
+                .map(|pattern| regex::RegexBuilder::new(pattern).multi_line(true).build().map_err(|e| anyhow::anyhow!("Invalid --exclude-matching pattern '{}': {}", pattern, e)))
+                .transpose()?,
+            matching_excluded_count: AtomicUsize::new(0),
+            encoding: cli.encoding.as_deref()
+                .map(|label| encoding_rs::Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown encoding '{}'", label)))
+                .transpose()?,
+            binary_max_size: parse_size(&cli.binary_max_size)?,
+            binary_threshold: match cli.binary_threshold {
+                percent if (0.0..=100.0).contains(&percent) => percent,
+                percent => return Err(anyhow::anyhow!("--binary-threshold must be between 0 and 100, got {}", percent)),
+            },
+            binary_files: Mutex::new(HashSet::new()),
+        })
+    }
+}
+
+// Keeps only lines matching `pattern` plus `context` lines around each, with `...`
+// marking the lines skipped between separate excerpts
+fn grep_excerpt(content: &str, pattern: &Regex, context: usize) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut keep = vec![false; lines.len()];
+    
+    for (i, line) in lines.iter().enumerate() {
+        if pattern.is_match(line) {
+            let end = (i + context).min(lines.len() - 1);
+            keep[i.saturating_sub(context)..=end].fill(true);
+        }
+    }
+    
+    let mut excerpt = String::new();
+    let mut previous_kept = None;
+    for (i, line) in lines.iter().enumerate().filter(|(i, _)| keep[*i]) {
+        if previous_kept.is_some_and(|prev| prev + 1 != i) || (previous_kept.is_none() && i > 0) {
+            excerpt.push_str("...\n");
+        }
+        excerpt.push_str(line);
+        excerpt.push('\n');
+        previous_kept = Some(i);
+    }
+    
+    if previous_kept.is_some_and(|prev| prev + 1 < lines.len()) {
+        excerpt.push_str("...\n");
+    }
+    
+    excerpt
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Exclusion {
+    IgnoreFile,
+    AutoExcluded,
+    ModifiedTime,
+    UnchangedSince,
+    IncludeFilter,
+    ExcludeFilter,
+    IncludeGlob,
+    ExcludeGlob,
+    IncludeType,
+    ExcludeType,
+    OutputFile,
+    FileCap,
+    NotSelected,
+    BelowMinSize,
+    AboveMaxSize,
+    Unreadable,
+    Binary,
+    BinaryTooLarge,
+    Empty,
+    NoGrepMatch,
+    ExcludeMatching,
+    SizeBudget,
+    TokenBudget,
+    ContentDepth,
+    LargeFile,
+    SecretFile,
+}
+
+impl Exclusion {
+    fn describe(self) -> &'static str {
+        match self {
+            Exclusion::IgnoreFile => "ignored by .gitignore/.fclipignore/--ignore-file",
+            Exclusion::AutoExcluded => "auto-excluded (--auto-exclude-common)",
+            Exclusion::ModifiedTime => "not modified within --modified-within",
+            Exclusion::UnchangedSince => "unchanged since the --since revision",
+            Exclusion::IncludeFilter => "not matching --include",
+            Exclusion::ExcludeFilter => "matching --exclude",
+            Exclusion::IncludeGlob => "not matching --include-glob",
+            Exclusion::ExcludeGlob => "matching --exclude-glob",
+            Exclusion::IncludeType => "not matching --include-type",
+            Exclusion::ExcludeType => "matching --exclude-type",
+            Exclusion::OutputFile => "fclip's own output file",
+            Exclusion::FileCap => "over the --max-files cap",
+            Exclusion::NotSelected => "not selected in --interactive",
+            Exclusion::BelowMinSize => "smaller than --min-file-size",
+            Exclusion::AboveMaxSize => "larger than --max-file-size",
+            Exclusion::Unreadable => "unreadable",
+            Exclusion::Binary => "binary",
+            Exclusion::BinaryTooLarge => "binary larger than --binary-max-size",
+            Exclusion::Empty => "empty (--exclude-empty)",
+            Exclusion::NoGrepMatch => "no --grep match",
+            Exclusion::ExcludeMatching => "content matching --exclude-matching",
+            Exclusion::SizeBudget => "over the --max-size budget",
+            Exclusion::TokenBudget => "over the --max-tokens budget",
+            Exclusion::ContentDepth => "below --content-depth (structure only)",
+            Exclusion::LargeFile => "over --auto-skip-large-tokens (stub only)",
+            Exclusion::SecretFile => "secret-looking file name (--block-secrets)",
+        }
+    }
+}
+
+#[derive(Default)]
+struct ExclusionReport {
+    by_reason: BTreeMap<Exclusion, Vec<PathBuf>>,
+}
+
+impl ExclusionReport {
+    fn record(&mut self, reason: Exclusion, path: &Path) {
+        self.by_reason.entry(reason).or_default().push(path.to_path_buf());
+    }
+
+    fn paths(&self, reason: Exclusion) -> Vec<PathBuf> {
+        self.by_reason.get(&reason).cloned().unwrap_or_default()
+    }
+
+    // Prints only with --report-excluded; lists a few example paths per reason, or all with --verbose
+    fn report(&self, cli: &Config) {
+        const EXAMPLES: usize = 5;
+        
+        if !cli.report_excluded {
+            return;
+        }
+        
+        let total: usize = self.by_reason.values().map(Vec::len).sum();
+        eprintln!("\n=== EXCLUDED FILES ({}) ===", total);
+        for (reason, paths) in &self.by_reason {
+            eprintln!("{} ({}):", reason.describe(), paths.len());
+            let shown = if cli.verbose { paths.len() } else { EXAMPLES.min(paths.len()) };
+            for path in &paths[..shown] {
+                eprintln!("  {}", path.display());
+            }
+            if shown < paths.len() {
+                eprintln!("  ... and {} more", paths.len() - shown);
+            }
+        }
+    }
+}
+
+fn process_single_file(file_path: &Path, cli: &Config, state: &RunState, options: &ProcessingOptions) -> Result<String, Exclusion> {
+    if options.min_file_size.is_some() || options.max_file_size.is_some() {
+        match fs::metadata(file_path) {
+            Ok(metadata) => {
+                let file_size = metadata.len() as usize;
+                if let Some(min_size) = options.min_file_size {
+                    if file_size < min_size {
+                        if cli.verbose {
+                            eprintln!("Skipping {} - {} bytes is below minimum file size of {} bytes",
+                                    file_path.display(), file_size, min_size);
+                        }
+                        return Err(Exclusion::BelowMinSize);
+                    }
+                }
+                if let Some(max_size) = options.max_file_size {
+                    if file_size > max_size {
+                        if cli.verbose {
+                            eprintln!("Skipping {} - {} bytes exceeds maximum file size of {} bytes",
+                                    file_path.display(), file_size, max_size);
+                        }
+                        return Err(Exclusion::AboveMaxSize);
+                    }
+                }
+            }
+            Err(e) => {
+                if !cli.quiet {
+                    eprintln!("Warning: Cannot read metadata for {}: {}", file_path.display(), e);
+                }
+                return Err(Exclusion::Unreadable);
+            }
+        }
+    }
+
+    if cli.verbose {
+        eprintln!("Processing: {}", file_path.display());
+    }
+    
+    let bytes = match fs::read(file_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            if !cli.quiet {
+                eprintln!("Warning: Cannot read file {}: {}", file_path.display(), e);
+            }
+            return Err(Exclusion::Unreadable);
+        }
+    };
+    let mut content = match decode_file_content(file_path, bytes, cli, options) {
+        DecodedFile::Text(content) => content,
+        // Base64 bodies skip the text pipeline: line ranges, grep and redaction would only corrupt them
+        DecodedFile::Binary(bytes) => return encode_binary(file_path, &bytes, cli, options),
+    };
+    
+    if cli.exclude_empty && content.trim().is_empty() {
+        if cli.verbose {
+            eprintln!("Skipping empty file: {}", file_path.display());
+        }
+        return Err(Exclusion::Empty);
+    }
+
+    if content.starts_with('\u{FEFF}') && !cli.preserve_bytes {
+        content = content.trim_start_matches('\u{FEFF}').to_string();
+    }
+    
+    // Everything below expects LF; --line-ending crlf converts back once the content is final
+    if cli.line_ending != LineEnding::Preserve && !cli.preserve_bytes {
+        content = content.replace("\r\n", "\n");
+    }
+    
+    // Checked against the whole file, since markers like `@generated` usually sit in a header
+    // that a line range would cut off
+    if let Some(ref pattern) = options.exclude_matching {
+        if let Some(found) = pattern.find(&content) {
+            if cli.verbose {
+                eprintln!("Skipping {} - content matches --exclude-matching ('{}')", file_path.display(), found.as_str().trim());
+            }
+            options.matching_excluded_count.fetch_add(1, Ordering::Relaxed);
+            return Err(Exclusion::ExcludeMatching);
+        }
+    }
+    
+    if let Some(range) = state.line_ranges.get(file_path) {
+        let total_lines = content.lines().count();
+        if range.start > total_lines && !cli.quiet {
+            eprintln!("Warning: {} has only {} lines; {} selects nothing", 
+                    file_path.display(), total_lines, range);
+        }
+        content = range.select(&content);
+    }
+    
+    if let Some(ref grep) = options.grep {
+        if !grep.is_match(&content) {
+            if cli.verbose {
+                eprintln!("Skipping {} - no match for --grep", file_path.display());
+            }
+            options.grep_excluded_count.fetch_add(1, Ordering::Relaxed);
+            return Err(Exclusion::NoGrepMatch);
+        }
+        if let Some(context) = cli.grep_context {
+            content = grep_excerpt(&content, grep, context);
+        }
+    }
+    
+    if cli.truncate_lines.is_some() || cli.truncate_tokens.is_some() {
+        content = truncate_content(content, cli.truncate_lines, cli.truncate_tokens);
+    }
+    
+    if let Some(max_tokens) = cli.per_file_tokens {
+        content = truncate_middle(content, max_tokens);
+    }
+    
+    // Two trailing spaces are a hard line break in markdown, so it keeps them
+    if cli.trim_trailing && !matches!(lang_for_path(file_path, &content), "markdown") {
+        let trimmed = trim_trailing_whitespace(&content);
+        options.trimmed_bytes.fetch_add(content.len() - trimmed.len(), Ordering::Relaxed);
+        content = trimmed;
+    }
+    
+    if let Some(ref redactor) = options.redactor {
+        let (redacted, count) = redactor.redact(&content);
+        if count > 0 {
+            if cli.verbose {
+                eprintln!("Redacted {} secret(s) in {}", count, file_path.display());
+            }
+            options.redaction_count.fetch_add(count, Ordering::Relaxed);
+            content = redacted;
+        }
+    }
+    
+    if output_crlf(cli) {
+        content = to_crlf(&content);
+    }
+    
+    Ok(content)
+}
+
+// --line-ending crlf, unless --preserve-bytes keeps every file as it is on disk
+fn output_crlf(cli: &Config) -> bool {
+    cli.line_ending == LineEnding::Crlf && !cli.preserve_bytes
+}
+
+// Turns every LF not already preceded by CR into CRLF
+fn to_crlf(text: &str) -> String {
+    let mut converted = String::with_capacity(text.len() + text.len() / 32);
+    let mut previous = '\0';
+    for c in text.chars() {
+        if c == '\n' && previous != '\r' {
+            converted.push('\r');
+        }
+        converted.push(c);
+        previous = c;
+    }
+    converted
+}
+
+// Cuts `content` at a line boundary so it has at most `max_lines` lines and roughly
+// `max_tokens` tokens, appending a marker saying how much was dropped
+fn truncate_content(content: String, max_lines: Option<usize>, max_tokens: Option<usize>) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut keep = max_lines.unwrap_or(lines.len()).min(lines.len());
+    
+    if let Some(max_tokens) = max_tokens {
+        let tokens = estimate_tokens(&content);
+        if tokens > max_tokens {
+            // Scale the byte budget by the file's own chars-per-token ratio
+            let byte_budget = content.len() * max_tokens / tokens;
+            let mut used = 0;
+            let fitting = lines.iter()
+                .take_while(|line| {
+                    used += line.len() + 1;
+                    used <= byte_budget
+                })
+                .count();
+            keep = keep.min(fitting);
+        }
+    }
+    
+    if keep >= lines.len() {
+        return content;
+    }
+    
+    let mut truncated = lines[..keep].join("\n");
+    if keep > 0 {
+        truncated.push('\n');
+    }
+    truncated.push_str(&format!("... (truncated, {} more lines)\n", lines.len() - keep));
+    truncated
+}
+
+// Keeps about `max_tokens` tokens of `content`, split evenly between whole lines from the
+// start and the end, so signatures at the top and exports at the bottom both survive
+fn truncate_middle(content: String, max_tokens: usize) -> String {
+    let tokens = estimate_tokens(&content);
+    if tokens <= max_tokens {
+        return content;
+    }
+    
+    let lines: Vec<&str> = content.lines().collect();
+    // Scale the byte budget by the file's own chars-per-token ratio, like truncate_content
+    let half_budget = content.len() * max_tokens / tokens / 2;
+    let fitting = |lines: &mut dyn Iterator<Item = &&str>| {
+        let mut used = 0;
+        lines.take_while(|line| {
+            used += line.len() + 1;
+            used <= half_budget
+        }).count()
+    };
+    let head = fitting(&mut lines.iter());
+    let tail = fitting(&mut lines.iter().rev()).min(lines.len() - head);
+    if head + tail >= lines.len() {
+        return content;
+    }
+    
+    let omitted = estimate_tokens(&lines[head..lines.len() - tail].join("\n"));
+    let mut truncated = String::with_capacity(half_budget * 2 + 64);
+    for line in &lines[..head] {
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    truncated.push_str(&format!("... ({} tokens omitted from middle) ...\n", omitted));
+    for line in &lines[lines.len() - tail..] {
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    truncated
+}
+
+enum DecodedFile {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+fn decode_file_content(file_path: &Path, bytes: Vec<u8>, cli: &Config, options: &ProcessingOptions) -> DecodedFile {
+    if let Some(encoding) = options.encoding {
+        let is_utf16 = encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE;
+        if !is_utf16 && is_likely_binary(&bytes, options.binary_threshold) {
+            return DecodedFile::Binary(bytes);
+        }
+        return DecodedFile::Text(transcode(file_path, &bytes, encoding, "--encoding", cli));
+    }
+    
+    // Checked before UTF-8 because ASCII-range UTF-16 is technically valid UTF-8 full of NULs
+    if cli.binary_null_tolerant {
+        if let Some(encoding) = detect_bomless_utf16(&bytes) {
+            return DecodedFile::Text(transcode(file_path, &bytes, encoding, "NUL byte pattern", cli));
+        }
+    }
+    
+    let bytes = match String::from_utf8(bytes) {
+        Ok(content) => return DecodedFile::Text(content),
+        Err(e) => e.into_bytes(),
+    };
+    
+    // UTF-16 text is full of NUL bytes, so a byte order mark has to be checked before the binary heuristic
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(&bytes) {
+        return DecodedFile::Text(transcode(file_path, &bytes, encoding, "byte order mark", cli));
+    }
+    
+    if is_likely_binary(&bytes, options.binary_threshold) {
+        return DecodedFile::Binary(bytes);
+    }
+    
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(&bytes, true);
+    let encoding = detector.guess(None, true);
+    DecodedFile::Text(transcode(file_path, &bytes, encoding, "detected", cli))
+}
+
+// Base64 body for --include-binary, wrapped so no single line gets unreasonably long
+fn encode_binary(file_path: &Path, bytes: &[u8], cli: &Config, options: &ProcessingOptions) -> Result<String, Exclusion> {
+    use base64::Engine;
+    
+    if !cli.include_binary {
+        if cli.verbose {
+            eprintln!("Skipping binary file: {}", file_path.display());
+        }
+        return Err(Exclusion::Binary);
+    }
+    
+    if bytes.len() > options.binary_max_size {
+        if !cli.quiet {
+            eprintln!("Skipping binary file {} - {} bytes exceeds --binary-max-size of {} bytes", 
+                    file_path.display(), bytes.len(), options.binary_max_size);
+        }
+        return Err(Exclusion::BinaryTooLarge);
+    }
+    
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let mut body = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for chunk in encoded.as_bytes().chunks(76) {
+        body.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        body.push('\n');
+    }
+    
+    options.binary_files.lock().unwrap_or_else(|e| e.into_inner()).insert(file_path.to_path_buf());
+    Ok(body)
+}
+
+fn transcode(file_path: &Path, bytes: &[u8], encoding: &'static encoding_rs::Encoding, source: &str, cli: &Config) -> String {
+    let (content, _, had_errors) = encoding.decode(bytes);
+    if had_errors && !cli.quiet {
+        eprintln!("Warning: {} is not valid {}; undecodable bytes were replaced", 
+                file_path.display(), encoding.name());
+    }
+    if cli.verbose {
+        eprintln!("Transcoded {} from {} ({})", file_path.display(), encoding.name(), source);
+    }
+    content.into_owned()
+}
+
+struct ModelInfo {
+    name: &'static str,
+    input_price_per_mtok: f64,
+    context_window: usize,
+}
+
+const MODELS: &[ModelInfo] = &[
+    ModelInfo { name: "gpt-4o", input_price_per_mtok: 2.50, context_window: 128_000 },
+    ModelInfo { name: "gpt-4o-mini", input_price_per_mtok: 0.15, context_window: 128_000 },
+    ModelInfo { name: "gpt-4", input_price_per_mtok: 30.00, context_window: 8_192 },
+    ModelInfo { name: "gpt-4-turbo", input_price_per_mtok: 10.00, context_window: 128_000 },
+    ModelInfo { name: "o1", input_price_per_mtok: 15.00, context_window: 200_000 },
+    ModelInfo { name: "claude-3.5-sonnet", input_price_per_mtok: 3.00, context_window: 200_000 },
+    ModelInfo { name: "claude-3.5-haiku", input_price_per_mtok: 0.80, context_window: 200_000 },
+    ModelInfo { name: "claude-3-opus", input_price_per_mtok: 15.00, context_window: 200_000 },
+    ModelInfo { name: "gemini-1.5-pro", input_price_per_mtok: 1.25, context_window: 2_000_000 },
+    ModelInfo { name: "gemini-1.5-flash", input_price_per_mtok: 0.075, context_window: 1_000_000 },
+];
+
+fn find_model(name: &str) -> Result<&'static ModelInfo> {
+    MODELS.iter()
+        .find(|model| model.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let known: Vec<_> = MODELS.iter().map(|model| model.name).collect();
+            anyhow::anyhow!("Unknown model '{}' (known models: {})", name, known.join(", "))
+        })
+}
+
+// Warns when `output_tokens` won't fit in --model's context window, failing instead
+// under --fail-if-over-context
+fn check_context_window(output_tokens: usize, model: Option<&ModelInfo>, cli: &Config) -> Result<()> {
+    let Some(model) = model.filter(|model| output_tokens > model.context_window) else {
+        return Ok(());
+    };
+    let message = format!("Output of ~{} tokens exceeds the {}-token context window of {} by ~{} tokens",
+                          output_tokens, model.context_window, model.name, output_tokens - model.context_window);
+    if cli.fail_if_over_context {
+        return Err(anyhow::anyhow!("{}", message));
+    }
+    if !cli.quiet {
+        eprintln!("\nWARNING: {}.", message);
+        eprintln!("Try --max-tokens {} to keep the most important files, or --compress to shrink them.", model.context_window);
+    }
+    Ok(())
+}
+
+fn resolve_cost_rate(cli: &Config) -> Result<Option<(String, f64)>> {
+    if let Some(rate) = cli.cost_per_mtok {
+        if rate < 0.0 {
+            return Err(anyhow::anyhow!("Cost per million tokens cannot be negative"));
+        }
+        let label = cli.cost_model.clone().unwrap_or_else(|| "custom rate".to_string());
+        return Ok(Some((label, rate)));
+    }
+    
+    match &cli.cost_model {
+        Some(name) => {
+            let model = find_model(name)?;
+            Ok(Some((model.name.to_string(), model.input_price_per_mtok)))
+        }
+        None => Ok(None),
+    }
+}
+
+// Where the tokens went after selection: the prompt, structure and dependency sections, each
+// included file, and the files the budgets cut
+fn print_budget_report(files: &[(PathBuf, String)], cli: &Config, state: &RunState) {
+    let prompt_tokens = state.prompt.as_deref().map_or(0, estimate_tokens);
+    let structure_tokens = if include_structure(cli) {
+        let tree_entries = structure_entries(files, cli, state);
+        estimate_tokens(&generate_directory_tree(&state.paths, &tree_entries, cli))
+    } else {
+        0
+    };
+    let dependency_tokens = if cli.include_dependencies {
+        estimate_tokens(&find_dependencies(&state.paths))
+    } else {
+        0
+    };
+    
+    let mut file_tokens: Vec<_> = files.iter()
+        .filter(|(path, _)| !state.referenced_files.contains_key(path))
+        .map(|(path, content)| (path, file_tokens(path, content, state)))
+        .collect();
+    file_tokens.sort_by_key(|&(path, tokens)| (std::cmp::Reverse(tokens), path.clone()));
+    let files_total: usize = file_tokens.iter().map(|(_, tokens)| tokens).sum();
+    let used = prompt_tokens + files_total;
+    
+    eprintln!("\n=== TOKEN BUDGET ===");
+    match cli.max_tokens {
+        Some(max_tokens) if max_tokens > 0 => eprintln!("Budget: {} tokens (--max-tokens), ~{} used ({:.0}%)", 
+                                                        max_tokens, used, used as f64 * 100.0 / max_tokens as f64),
+        _ => eprintln!("Budget: no --max-tokens limit, ~{} used", used),
+    }
+    if state.prompt.is_some() {
+        eprintln!("  Prompt: ~{} tokens", prompt_tokens);
+    }
+    eprintln!("  Files: ~{} tokens in {} file(s)", files_total, file_tokens.len());
+    for (path, tokens) in &file_tokens {
+        eprintln!("    {} (~{} tokens)", styled_path(path, cli), tokens);
+    }
+    // The budget only covers the prompt and file contents; these sections come on top
+    if include_structure(cli) {
+        eprintln!("  Structure: ~{} tokens (outside the budget)", structure_tokens);
+    }
+    if cli.include_dependencies {
+        eprintln!("  Dependencies: ~{} tokens (outside the budget)", dependency_tokens);
+    }
+    
+    if state.budget_cuts.is_empty() {
+        eprintln!("Cut: nothing");
+    } else {
+        let cut_total: usize = state.budget_cuts.iter().map(|(_, tokens)| tokens).sum();
+        eprintln!("Cut: ~{} tokens in {} file(s)", cut_total, state.budget_cuts.len());
+        for (path, tokens) in &state.budget_cuts {
+            eprintln!("    {} (~{} tokens)", styled_path(path, cli), tokens);
+        }
+    }
+}
+
+// Shows the --include-structure and --include-dependencies sections a real run would emit,
+// each with its token cost
+fn print_section_previews(files: &[(PathBuf, String)], cli: &Config, state: &RunState) {
+    if include_structure(cli) {
+        let tree_entries = structure_entries(files, cli, state);
+        let tree = generate_directory_tree(&state.paths, &tree_entries, cli);
+        eprintln!("\n=== Structure section (~{} tokens) ===", estimate_tokens(&tree));
+        eprint!("{}", tree);
+    }
+    
+    if cli.include_dependencies {
+        let deps = find_dependencies(&state.paths);
+        if deps.is_empty() {
+            eprintln!("\n=== Dependencies section: no manifests found ===");
+        } else {
+            eprintln!("\n=== Dependencies section (~{} tokens) ===", estimate_tokens(&deps));
+            eprint!("{}", deps);
+        }
+    }
+}
+
+fn report_processing(options: &ProcessingOptions, cli: &Config) {
+    if cli.quiet {
+        return;
+    }
+    if options.redactor.is_some() {
+        eprintln!("Redacted {} secret(s).", options.redaction_count.load(Ordering::Relaxed));
+    }
+    if cli.trim_trailing {
+        eprintln!("Trimmed {} of trailing whitespace.", format_size(options.trimmed_bytes.load(Ordering::Relaxed)));
+    }
+    if let Some(grep) = &options.grep {
+        eprintln!("Excluded {} file(s) not matching --grep '{}'.", 
+                 options.grep_excluded_count.load(Ordering::Relaxed), grep);
+    }
+    if let Some(pattern) = &options.exclude_matching {
+        eprintln!("Excluded {} file(s) matching --exclude-matching '{}'.", 
+                 options.matching_excluded_count.load(Ordering::Relaxed), pattern);
+    }
+}
+
+type CommentSyntax = (&'static [&'static str], Option<(&'static str, &'static str)>);
+
+// Line comment prefixes and block comment delimiters for the languages `--stats` can
+// estimate a comment ratio for
+fn comment_syntax(path: &Path) -> Option<CommentSyntax> {
+    const C_LIKE: CommentSyntax = (&["//"], Some(("/*", "*/")));
+    const HASH: CommentSyntax = (&["#"], None);
+    const MARKUP: CommentSyntax = (&[], Some(("<!--", "-->")));
+    
+    match lang_for_ext(path.extension().and_then(|e| e.to_str()).unwrap_or("")) {
+        "rust" | "javascript" | "jsx" | "typescript" | "tsx" | "go" | "java" | "kotlin" | "scala"
+        | "c" | "cpp" | "csharp" | "swift" | "dart" | "css" | "scss" | "protobuf" => Some(C_LIKE),
+        "php" => Some((&["//", "#"], Some(("/*", "*/")))),
+        "python" | "ruby" | "elixir" | "bash" | "yaml" | "toml" | "graphql" | "dockerfile" => Some(HASH),
+        "hcl" => Some((&["#", "//"], Some(("/*", "*/")))),
+        "powershell" => Some((&["#"], Some(("<#", "#>")))),
+        "sql" => Some((&["--"], Some(("/*", "*/")))),
+        "lua" => Some((&["--"], Some(("--[[", "]]")))),
+        "haskell" => Some((&["--"], Some(("{-", "-}")))),
+        "html" | "xml" | "vue" | "svelte" => Some(MARKUP),
+        _ => None,
+    }
+}
+
+// Counts (comment, code) lines, ignoring blank ones. A line is a comment when it starts
+// with one (or sits inside a block comment), so trailing comments count as code.
+fn count_comment_lines(content: &str, (line_prefixes, block): CommentSyntax) -> (usize, usize) {
+    let mut comment_lines = 0;
+    let mut code_lines = 0;
+    let mut block_end: Option<&str> = None;
+    
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(end) = block_end {
+            comment_lines += 1;
+            if line.contains(end) {
+                block_end = None;
+            }
+            continue;
+        }
+        
+        // Block openers are checked first so `--[[` isn't taken for a `--` line comment
+        if let Some((start, end)) = block.filter(|(start, _)| line.starts_with(start)) {
+            comment_lines += 1;
+            if !line[start.len()..].contains(end) {
+                block_end = Some(end);
+            }
+        } else if line_prefixes.iter().any(|prefix| line.starts_with(prefix)) {
+            comment_lines += 1;
+        } else {
+            code_lines += 1;
+        }
+    }
+    
+    (comment_lines, code_lines)
+}
+
+fn comment_percent(comment_lines: usize, code_lines: usize) -> f64 {
+    match comment_lines + code_lines {
+        0 => 0.0,
+        total => comment_lines as f64 * 100.0 / total as f64,
+    }
+}
+
+fn print_stats(files_data: &[(PathBuf, String)], total_size: usize, total_tokens: usize, cli: &Config, state: &RunState) -> Result<()> {
+    let mut ext_counts: HashMap<String, usize> = HashMap::new();
+    let mut ext_sizes: HashMap<String, usize> = HashMap::new();
+    let mut ext_tokens: HashMap<String, usize> = HashMap::new();
+    let mut dir_counts: HashMap<String, usize> = HashMap::new();
+    let mut dir_sizes: HashMap<String, usize> = HashMap::new();
+    let mut dir_tokens: HashMap<String, usize> = HashMap::new();
+    // (comment, code) lines, only for extensions with a known comment syntax
+    let mut ext_comments: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut total_lines = 0;
+    let mut total_chars = 0;
+    
+    for (path, content) in files_data {
+        let ext = path.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("(no extension)")
+            .to_string();
+        
+        let tokens = file_tokens(path, content, state);
+        
+        *ext_counts.entry(ext.clone()).or_insert(0) += 1;
+        *ext_sizes.entry(ext.clone()).or_insert(0) += content.len();
+        if let Some(syntax) = comment_syntax(path).filter(|_| !state.binary_files.contains(path)) {
+            let (comment_lines, code_lines) = count_comment_lines(content, syntax);
+            let counts = ext_comments.entry(ext.clone()).or_insert((0, 0));
+            counts.0 += comment_lines;
+            counts.1 += code_lines;
+        }
+        *ext_tokens.entry(ext).or_insert(0) += tokens;
+        
+        let dir = stats_directory(path, cli, state);
+        *dir_counts.entry(dir.clone()).or_insert(0) += 1;
+        *dir_sizes.entry(dir.clone()).or_insert(0) += content.len();
+        *dir_tokens.entry(dir).or_insert(0) += tokens;
+        total_lines += content.lines().count();
+        total_chars += content.chars().count();
+    }
+    
+    let mut ext_data: Vec<_> = ext_counts.iter().collect();
+    ext_data.sort_by_key(|&(ext, count)| (std::cmp::Reverse(*count), ext.clone()));
+    
+    let mut dir_data: Vec<_> = dir_tokens.iter().collect();
+    dir_data.sort_by_key(|&(dir, tokens)| (std::cmp::Reverse(*tokens), dir.clone()));
+    
+    let mut file_tokens: Vec<_> = files_data.iter()
+        .map(|(path, content)| (path, content.len(), file_tokens(path, content, state)))
+        .collect();
+    file_tokens.sort_by_key(|&(_, _, tokens)| std::cmp::Reverse(tokens));
+    file_tokens.truncate(cli.top);
+    
+    let (total_comment_lines, total_code_lines) = ext_comments.values()
+        .fold((0, 0), |(comments, code), &(c, l)| (comments + c, code + l));
+    
+    if matches!(cli.stats_format, StatsFormat::Json) {
+        let token_density = if total_chars > 0 {
+            total_tokens as f64 / total_chars as f64
+        } else {
+            0.0
+        };
+        
+        let stats_json = serde_json::json!({
+            "total_files": files_data.len(),
+            "total_size": total_size,
+            "total_tokens": total_tokens,
+            "total_lines": total_lines,
+            "total_chars": total_chars,
+            "token_density": token_density,
+            "comment_lines": total_comment_lines,
+            "code_lines": total_code_lines,
+            "comment_percent": comment_percent(total_comment_lines, total_code_lines),
+            "by_extension": ext_data.iter().map(|&(ext, count)| {
+                let mut entry = serde_json::json!({
+                    "extension": ext,
+                    "files": count,
+                    "size": ext_sizes[ext],
+                    "tokens": ext_tokens[ext]
+                });
+                if let Some(&(comment_lines, code_lines)) = ext_comments.get(ext) {
+                    entry["comment_lines"] = serde_json::json!(comment_lines);
+                    entry["code_lines"] = serde_json::json!(code_lines);
+                    entry["comment_percent"] = serde_json::json!(comment_percent(comment_lines, code_lines));
+                }
+                entry
+            }).collect::<Vec<_>>(),
+            "by_directory": dir_data.iter().map(|&(dir, tokens)| serde_json::json!({
+                "directory": dir,
+                "files": dir_counts[dir],
+                "size": dir_sizes[dir],
+                "tokens": tokens
+            })).collect::<Vec<_>>(),
+            "top_files": file_tokens.iter().map(|(path, size, tokens)| serde_json::json!({
+                "path": styled_path(path, cli),
+                "size": size,
+                "tokens": tokens
+            })).collect::<Vec<_>>()
+        });
+        
+        let rendered = serde_json::to_string_pretty(&stats_json)?;
+        if let Some(stats_file) = &cli.stats_file {
+            fs::write(stats_file, rendered + "\n")?;
+            if !cli.quiet {
+                eprintln!("Statistics written to: {}", stats_file.display());
+            }
+        } else {
+            println!("{}", rendered);
+        }
+        return Ok(());
+    }
+    
+    eprintln!("\n=== STATISTICS ===");
+    eprintln!("Total files: {}", files_data.len());
+    eprintln!("Total size: {:.1} KB", total_size as f64 / 1024.0);
+    eprintln!("Total tokens: ~{}", total_tokens);
+    eprintln!("Total lines: {}", total_lines);
+    if total_comment_lines + total_code_lines > 0 {
+        eprintln!("Comment lines: {} of {} non-blank lines ({:.0}%)", 
+                 total_comment_lines, total_comment_lines + total_code_lines,
+                 comment_percent(total_comment_lines, total_code_lines));
+    }
+    eprintln!("\nBy file type:");
+    
+    for (ext, count) in ext_data {
+        let size_kb = ext_sizes[ext] as f64 / 1024.0;
+        let tokens = ext_tokens[ext];
+        match ext_comments.get(ext) {
+            Some(&(comment_lines, code_lines)) => eprintln!("  {}: {} files ({:.1} KB, ~{} tokens, {:.0}% comments)", 
+                                                            ext, count, size_kb, tokens, comment_percent(comment_lines, code_lines)),
+            None => eprintln!("  {}: {} files ({:.1} KB, ~{} tokens)", ext, count, size_kb, tokens),
+        }
+    }
+    
+    eprintln!("\nBy directory:");
+    for (dir, tokens) in dir_data {
+        let size_kb = dir_sizes[dir] as f64 / 1024.0;
+        eprintln!("  {}: {} files ({:.1} KB, ~{} tokens)", dir, dir_counts[dir], size_kb, tokens);
+    }
+    
+    if !file_tokens.is_empty() {
+        eprintln!("\nTOP FILES BY TOKENS:");
+        for (path, size, tokens) in file_tokens {
+            eprintln!("  {} ({} bytes, ~{} tokens)", styled_path(path, cli), size, tokens);
+        }
+    }
+    
+    Ok(())
+}
+
+// The directory `path` is counted under in --stats: its input path plus at most
+// --stats-depth directory levels below it
+fn stats_directory(path: &Path, cli: &Config, state: &RunState) -> String {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let root = state.paths.iter()
+        .find(|root| path.starts_with(root) && path != root.as_path())
+        .map_or(parent, PathBuf::as_path);
+    let relative_dir = parent.strip_prefix(root).unwrap_or(Path::new(""));
+    let prefix: PathBuf = relative_dir.components().take(cli.stats_depth).collect();
+    styled_path(&root.join(prefix), cli)
+}
+
+// Response files may name further response files, up to this many levels deep
+const MAX_ARGFILE_DEPTH: usize = 8;
+
+// Replaces each `@FILE` argument naming an existing file with the arguments read from it.
+// Anything else starting with `@`, such as a `@types` directory, is passed through unchanged.
+fn expand_argfiles(args: impl IntoIterator<Item = std::ffi::OsString>, depth: usize) -> Result<Vec<std::ffi::OsString>> {
+    let mut expanded = Vec::new();
+    
+    for arg in args {
+        let argfile = arg.to_str()
+            .and_then(|arg| arg.strip_prefix('@'))
+            .map(PathBuf::from)
+            .filter(|path| path.is_file());
+        let Some(argfile) = argfile else {
+            expanded.push(arg);
+            continue;
+        };
+        
+        if depth >= MAX_ARGFILE_DEPTH {
+            return Err(anyhow::anyhow!("Response files nested more than {} levels deep at @{}", MAX_ARGFILE_DEPTH, argfile.display()));
+        }
+        let text = fs::read_to_string(&argfile)
+            .map_err(|e| anyhow::anyhow!("Cannot read response file {}: {}", argfile.display(), e))?;
+        let words = split_argfile(&text)
+            .map_err(|e| anyhow::anyhow!("Invalid response file {}: {}", argfile.display(), e))?;
+        expanded.extend(expand_argfiles(words.into_iter().map(Into::into), depth + 1)?);
+    }
+    
+    Ok(expanded)
+}
+
+// Splits response file text into arguments at whitespace. Single quotes keep their content
+// literally, double quotes allow `\"` and `\\` escapes, and a backslash outside quotes escapes
+// the next character. Lines whose first non-blank character is `#` are comments.
+fn split_argfile(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    
+    for line in text.lines().filter(|line| !line.trim_start().starts_with('#')) {
+        let mut chars = line.chars();
+        let mut current: Option<String> = None;
+        
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {
+                    args.extend(current.take());
+                }
+                '\'' => {
+                    let word = current.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => return Err(format!("unterminated single quote in: {}", line)),
+                        }
+                    }
+                }
+                '"' => {
+                    let word = current.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some(c @ ('"' | '\\')) => word.push(c),
+                                Some(c) => {
+                                    word.push('\\');
+                                    word.push(c);
+                                }
+                                None => return Err(format!("unterminated double quote in: {}", line)),
+                            },
+                            Some(c) => word.push(c),
+                            None => return Err(format!("unterminated double quote in: {}", line)),
+                        }
+                    }
+                }
+                '\\' => {
+                    let word = current.get_or_insert_with(String::new);
+                    word.extend(chars.next());
+                }
+                c => current.get_or_insert_with(String::new).push(c),
+            }
+        }
+        args.extend(current);
+    }
+    
+    Ok(args)
+}
+
+/// Parses the command line (with `@FILE` response files expanded), layering it over any
+/// `fclip.toml` configuration. Exits on invalid arguments.
+pub fn parse_args() -> Result<Config> {
+    let args = expand_argfiles(std::env::args_os(), 0)?;
+    let matches = Config::command().get_matches_from(args);
+    Config::from_matches(&matches)
+}
+
+impl Config {
+    /// Builds a configuration from command-line style `args` (the first being the program
+    /// name) exactly as the fclip binary would, including `@FILE` response files and
+    /// `fclip.toml`. Invalid arguments, `--help` and `--version` come back as clap errors.
+    pub fn from_args<I, T>(args: I) -> Result<Config>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString>,
+    {
+        let args = expand_argfiles(args.into_iter().map(Into::into), 0)?;
+        let matches = Config::command().try_get_matches_from(args)?;
+        Config::from_matches(&matches)
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Result<Config> {
+        let mut cli = Config::from_arg_matches(matches)?;
+        load_config(&mut cli, matches)?;
+        if matches.value_source("max_size_mb") == Some(ValueSource::CommandLine) && cli.max_size.is_none() && !cli.quiet {
+            eprintln!("Warning: --max-size-mb is deprecated; use --max-size {}mb", cli.max_size_mb);
+        }
+        Ok(cli)
+    }
+}
+
+// Everything one collect/format pass works out from the options and the files, so `Config`
+// itself never changes and a new pass starts from nothing
+#[derive(Default)]
+struct RunState {
+    // `cli.paths` with their `:START-END` and `:DEPTH` suffixes split off
+    paths: Vec<PathBuf>,
+    line_ranges: HashMap<PathBuf, LineRange>,
+    path_depths: HashMap<PathBuf, usize>,
+    // Lowercased extension -> group name from --group-map and the config's [groups] table
+    custom_groups: HashMap<String, String>,
+    // --prompt, or the contents of --prompt-file
+    prompt: Option<String>,
+    // Files changed since the --since revision, loaded before walking
+    git_changes: git::GitChanges,
+    // Files below --content-depth, listed in the structure tree but not copied
+    structure_only_files: Vec<PathBuf>,
+    // Files whose content is a base64 body from --include-binary
+    binary_files: HashSet<PathBuf>,
+    // Token estimates of the processed files, counted once after reading
+    token_counts: HashMap<PathBuf, usize>,
+    // Files left out by --max-file-size or the budgets that --reference-oversized still lists,
+    // with their size on disk
+    referenced_files: HashMap<PathBuf, usize>,
+    // Token estimates of the stubs --auto-skip-large-tokens left in place of large files
+    skipped_large_files: HashMap<PathBuf, usize>,
+    // --dedup copies replaced by a reference, mapped to the file they repeat
+    duplicates: HashMap<PathBuf, PathBuf>,
+    // Files --max-size or --max-tokens left out, with their token estimates, for --budget-report
+    budget_cuts: Vec<(PathBuf, usize)>,
+    // Hash of the selected files for --fingerprint, computed once the selection is final
+    content_fingerprint: Option<String>,
+}
+
+impl RunState {
+    // Splits the path suffixes, parses --group-map, reads --prompt-file and loads the --since
+    // changes; the rest is filled in while walking and reading
+    fn new(cli: &Config) -> Result<RunState> {
+        let (paths, line_ranges) = split_line_ranges(&cli.paths);
+        let (paths, path_depths) = split_path_depths(&paths);
+        let prompt = match &cli.prompt_file {
+            Some(prompt_file) => Some(fs::read_to_string(prompt_file)
+                .map_err(|e| anyhow::anyhow!("Cannot read prompt file {}: {}", prompt_file.display(), e))?),
+            None => cli.prompt.clone(),
+        };
+        let git_changes = match &cli.since {
+            Some(revision) => git::GitChanges::load(&paths, revision, cli.with_diff, cli.quiet)?,
+            None => git::GitChanges::default(),
+        };
+        Ok(RunState {
+            custom_groups: parse_group_map(&cli.group_map)?,
+            paths,
+            line_ranges,
+            path_depths,
+            prompt,
+            git_changes,
+            ..RunState::default()
+        })
+    }
+}
+
+/// The files one [`collect_files`] pass selected, along with what it learned about them
+/// (line ranges, binary bodies, omitted-file stubs, duplicates, git status) that [`format`]
+/// needs to render them.
+pub struct Collection {
+    /// Each selected file with its processed content, in output order
+    pub files: Vec<(PathBuf, String)>,
+    state: RunState,
+}
+
+/// Walks `config.paths` and returns every selected file with its processed content, in output
+/// order and within the configured size and token budgets.
+pub fn collect_files(config: &Config) -> Result<Collection> {
+    let processing = ProcessingOptions::from_cli(config)?;
+    let pin_patterns = parse_glob_patterns(&config.pin)?;
+    let mut excluded = ExclusionReport::default();
+    let mut state = RunState::new(config)?;
+    let candidates = find_candidates(config, &state, None, &mut excluded)?;
+    state.structure_only_files = excluded.paths(Exclusion::ContentDepth);
+    let (files, _, _) = read_candidates(&candidates, config, &mut state, &processing, &pin_patterns, &mut excluded);
+    Ok(Collection { files, state })
+}
+
+/// Formats a [`collect_files`] result exactly as fclip would copy it. `config` should be the
+/// one the files were collected with.
+pub fn format(collection: &Collection, config: &Config) -> String {
+    format_output(&collection.files, &config.format, config, &collection.state)
+}
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Re-runs [`run`] whenever a file under `cli.paths` changes, until interrupted.
+pub fn watch(cli: &Config) -> Result<()> {
+    use notify::Watcher;
+    
+    let (paths, _) = split_line_ranges(&cli.paths);
+    let (paths, _) = split_path_depths(&paths);
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for path in &paths {
+        watcher.watch(path, notify::RecursiveMode::Recursive)
+            .map_err(|e| anyhow::anyhow!("Cannot watch {}: {}", path.display(), e))?;
+    }
+    
+    // fclip's own output files live inside the watched tree more often than not; reacting
+    // to them would re-copy forever
+    let output_file = cli.output_file.as_deref().map(|path| compressed_output_path(path, cli));
+    let own_outputs: Vec<PathBuf> = [output_file, cli.stats_file.clone()]
+        .into_iter()
+        .flatten()
+        .filter(|path| path.as_path() != Path::new("-"))
+        .filter_map(|path| std::path::absolute(path).ok())
+        .collect();
+    
+    if !cli.quiet {
+        eprintln!("Watching {} path(s) for changes (Ctrl-C to stop)...", paths.len());
+    }
+    
+    loop {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        let mut collect = |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event) if !matches!(event.kind, notify::EventKind::Access(_)) => {
+                    // components() also drops the interior `.` that watching "." leaves in event paths
+                    changed.extend(event.paths.into_iter()
+                        .filter(|path| is_watch_relevant(path, &own_outputs))
+                        .map(|path| path.components().collect::<PathBuf>()));
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Warning: watch error: {}", e),
+            }
+        };
+        
+        collect(rx.recv()?);
+        while let Ok(event) = rx.recv_timeout(WATCH_DEBOUNCE) {
+            collect(event);
+        }
+        
+        changed.sort();
+        changed.dedup();
+        let Some(first) = changed.first() else {
+            continue;
+        };
+        
+        if !cli.quiet {
+            eprintln!();
+            if changed.len() == 1 {
+                eprintln!("Changed: {}", first.display());
+            } else {
+                eprintln!("Changed: {} and {} other path(s)", first.display(), changed.len() - 1);
+            }
+        }
+        
+        // A failed pass (e.g. a file vanished mid-read) shouldn't end the session
+        if let Err(e) = run(cli) {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+}
+
+fn is_watch_relevant(path: &Path, own_outputs: &[PathBuf]) -> bool {
+    let in_vcs_dir = path.components()
+        .any(|component| matches!(component.as_os_str().to_str(), Some(".git" | ".hg" | ".svn")));
+    if in_vcs_dir {
+        return false;
+    }
+    
+    let Ok(path) = std::path::absolute(path) else {
+        return true;
+    };
+    
+    !own_outputs.iter().any(|output| {
+        // --split-by-size writes `<stem>_part_NNN` siblings next to the output file
+        let stem = output.file_stem().map(|stem| format!("{}_part_", stem.to_string_lossy()));
+        path == *output || (path.parent() == output.parent()
+            && stem.is_some_and(|stem| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(&stem))))
+    })
+}
+
+// Walks on all cores and returns every file found, sorted so that nothing downstream
+// depends on the order the walker threads happened to finish in
+fn walk_files_parallel(walker: &WalkBuilder, verbose: bool, log_symlinks: bool) -> Vec<PathBuf> {
+    let files = Mutex::new(Vec::new());
+    
+    walker.build_parallel().run(|| {
+        let files = &files;
+        Box::new(move |result| {
+            let entry = match result {
+                Ok(e) => e,
+                Err(e) => {
+                    if verbose {
+                        eprintln!("Warning: {}", e);
+                    }
+                    return WalkState::Continue;
+                }
+            };
+            
+            if log_symlinks && entry.path_is_symlink() {
+                eprintln!("Following symlink: {}", entry.path().display());
+            }
+            
+            if entry.file_type().is_some_and(|ft| ft.is_file()) {
+                files.lock().unwrap_or_else(|e| e.into_inner()).push(entry.into_path());
+            }
+            WalkState::Continue
+        })
+    });
+    
+    let mut files = files.into_inner().unwrap_or_else(|e| e.into_inner());
+    files.sort();
+    files
+}
+
+/// A walker for `path` honoring the configured depth, symlink, hidden-file and ignore-file
+/// settings. Extension, glob, size and auto-exclude filters are applied after walking.
+pub fn build_walker(path: &Path, config: &Config) -> WalkBuilder {
+    let mut walker = unignored_walker(path, config);
+    walker
+        .git_ignore(config.use_gitignore)
+        .git_global(config.use_gitignore)
+        .git_exclude(config.use_gitignore);
+
+    // Later names take precedence in the ignore crate, so .dockerignore comes first and
+    // .fclipignore last
+    walker.add_custom_ignore_filename(DOCKERIGNORE_FILENAME);
+    for name in config.ignore_file.iter().filter(|name| !name.is_empty() && *name != DOCKERIGNORE_FILENAME) {
+        walker.add_custom_ignore_filename(name);
+    }
+    if config.use_fclipignore {
+        walker.add_custom_ignore_filename(FCLIPIGNORE_FILENAME);
+    }
+    walker
+}
+
+// build_walker without .gitignore rules or custom ignore files, for the --unignore pass.
+// WalkBuilder can't drop a custom ignore filename once added, so build_walker starts from this.
+fn unignored_walker(path: &Path, config: &Config) -> WalkBuilder {
+    let mut walker = WalkBuilder::new(path);
+    walker
+        .max_depth(config.depth)
+        .follow_links(config.follow_symlinks)
+        .hidden(!config.hidden)
+        .parents(!config.no_parent_ignore)
+        .git_ignore(false)
+        .git_global(false)
+        .git_exclude(false);
+
+    if config.hidden {
+        walker.filter_entry(|entry| !is_vcs_metadata_dir(entry.path()));
+    }
+    walker
+}
+
+// The per-file checks every candidate goes through, whether the walk found it or
+// --follow-imports reached it
+struct CandidateFilter<'a> {
+    cli: &'a Config,
+    state: &'a RunState,
+    output_file: Option<&'a Path>,
+    // The output file and, with --manifest, the manifest written next to it
+    output_files_canonical: Vec<PathBuf>,
+    include_globs: Vec<Pattern>,
+    exclude_globs: Vec<Pattern>,
+    include_types: Option<Types>,
+    exclude_types: Option<Types>,
+    files_considered: usize,
+    files_omitted_by_cap: usize,
+}
+
+impl CandidateFilter<'_> {
+    // Whether `file_path`, found under `root`, should be read; records why when it shouldn't
+    fn admit(&mut self, file_path: &Path, root: &Path, excluded: &mut ExclusionReport) -> bool {
+        if let Some(ref include_patterns) = self.cli.include {
+            if !should_include_file(file_path, include_patterns) {
+                if self.cli.verbose {
+                    eprintln!("Excluded by include filter: {}", file_path.display());
+                }
+                excluded.record(Exclusion::IncludeFilter, file_path);
+                return false;
+            }
+        }
+        
+        if let Some(ref exclude_patterns) = self.cli.exclude {
+            if should_exclude_file(file_path, exclude_patterns) {
+                if self.cli.verbose {
+                    eprintln!("Excluded by exclude filter: {}", file_path.display());
+                }
+                excluded.record(Exclusion::ExcludeFilter, file_path);
+                return false;
+            }
+        }
+
+        if self.cli.since.is_some() && self.state.git_changes.is_unchanged(file_path) {
+            if self.cli.verbose {
+                eprintln!("Excluded as unchanged since {}: {}", self.cli.since.as_deref().unwrap_or_default(), file_path.display());
+            }
+            excluded.record(Exclusion::UnchangedSince, file_path);
+            return false;
+        }
+        
+        if let Some(types) = &self.include_types {
+            if !types.matched(file_path, false).is_whitelist() {
+                if self.cli.verbose {
+                    eprintln!("Excluded by include type filter: {}", file_path.display());
+                }
+                excluded.record(Exclusion::IncludeType, file_path);
+                return false;
+            }
+        }
+        
+        if let Some(types) = &self.exclude_types {
+            if types.matched(file_path, false).is_whitelist() {
+                if self.cli.verbose {
+                    eprintln!("Excluded by exclude type filter: {}", file_path.display());
+                }
+                excluded.record(Exclusion::ExcludeType, file_path);
+                return false;
+            }
+        }
+
+        if !self.include_globs.is_empty() || !self.exclude_globs.is_empty() {
+            let relative_path = relative_path_str(file_path, root);
+
+            if !self.include_globs.is_empty() {
+                match find_matching_glob(&relative_path, &self.include_globs) {
+                    Some(pattern) => {
+                        if self.cli.verbose {
+                            eprintln!("Included by glob {}: {}", pattern, file_path.display());
+                        }
+                    }
+                    None => {
+                        if self.cli.verbose {
+                            eprintln!("Excluded by include glob filter: {}", file_path.display());
+                        }
+                        excluded.record(Exclusion::IncludeGlob, file_path);
+                        return false;
+                    }
+                }
+            }
+
+            if let Some(pattern) = find_matching_glob(&relative_path, &self.exclude_globs) {
+                if self.cli.verbose {
+                    eprintln!("Excluded by glob {}: {}", pattern, file_path.display());
+                }
+                excluded.record(Exclusion::ExcludeGlob, file_path);
+                return false;
+            }
+        }
+
+        if !self.output_files_canonical.is_empty() {
+            if let Ok(file_canonical) = file_path.canonicalize() {
+                if self.output_files_canonical.contains(&file_canonical) {
+                    if self.cli.verbose {
+                        eprintln!("Skipping output file: {}", file_path.display());
+                    }
+                    excluded.record(Exclusion::OutputFile, file_path);
+                    return false;
+                }
+            }
+        }
+
+        if let Some(output_file) = self.output_file {
+            if self.cli.split_by_size.is_some() {
+                let base_name = output_file.file_stem().unwrap_or_default().to_string_lossy();
+                let current_file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+                let is_chunk = current_file_name.starts_with(&*base_name) && current_file_name.contains("_part_");
+                if !base_name.is_empty() && (is_chunk || current_file_name == chunk_sums_filename(&base_name)) {
+                    if self.cli.verbose {
+                        eprintln!("Skipping chunked output file: {}", file_path.display());
+                    }
+                    excluded.record(Exclusion::OutputFile, file_path);
+                    return false;
+                }
+            }
+        }
+
+        if self.cli.block_secrets && is_secret_filename(file_path) {
+            if self.cli.verbose {
+                eprintln!("Blocked secret-looking file: {}", file_path.display());
+            }
+            excluded.record(Exclusion::SecretFile, file_path);
+            return false;
+        }
+
+        if let Some(content_depth) = self.cli.content_depth {
+            let depth = file_path.strip_prefix(root).map_or(0, |relative| relative.components().count());
+            if depth > content_depth {
+                if self.cli.verbose {
+                    eprintln!("Structure only (below --content-depth): {}", file_path.display());
+                }
+                excluded.record(Exclusion::ContentDepth, file_path);
+                return false;
+            }
+        }
+
+        if let Some(max_files) = self.cli.max_files {
+            if self.files_considered >= max_files {
+                self.files_omitted_by_cap += 1;
+                if self.cli.verbose {
+                    eprintln!("Omitted by file cap: {}", file_path.display());
+                }
+                excluded.record(Exclusion::FileCap, file_path);
+                return false;
+            }
+            self.files_considered += 1;
+        }
+
+        true
+    }
+}
+
+// Walks every path in `state.paths`, or follows --follow-imports instead, and applies the ignore
+// rules and filters, returning the files that should be read. `output_file` is skipped so a
+// dump never includes itself.
+fn find_candidates(cli: &Config, state: &RunState, output_file: Option<&Path>, excluded: &mut ExclusionReport) -> Result<Vec<PathBuf>> {
+    let mut filter = CandidateFilter {
+        cli,
+        state,
+        output_file,
+        output_files_canonical: output_file.into_iter()
+            .flat_map(|path| std::iter::once(path.to_path_buf()).chain(cli.manifest.then(|| manifest_path(path))))
+            .chain(parse_emit_targets(&cli.emit).unwrap_or_default().into_iter().map(|(_, path)| path))
+            .filter_map(|path| path.canonicalize().ok())
+            .collect(),
+        include_globs: parse_glob_patterns(&cli.include_glob)?,
+        exclude_globs: parse_glob_patterns(&cli.exclude_glob)?,
+        include_types: build_type_matcher(&cli.include_type)?,
+        exclude_types: build_type_matcher(&cli.exclude_type)?,
+        files_considered: 0,
+        files_omitted_by_cap: 0,
+    };
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    
+    let unignore_patterns = parse_glob_patterns(&cli.unignore)?;
+    let auto_excludes = resolve_auto_excludes(cli);
+    if cli.verbose && cli.auto_exclude_common {
+        eprintln!("Auto-exclude list: {}", auto_excludes.join(", "));
+    }
+    let modified_cutoff = match &cli.modified_within {
+        Some(duration_str) => {
+            let duration = parse_duration(duration_str)?;
+            Some(SystemTime::now().checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH))
+        }
+        None => None,
+    };
+
+    let followed = if cli.follow_imports.is_empty() {
+        Vec::new()
+    } else {
+        let files = imports::follow_imports(&cli.follow_imports, cli.import_depth, cli.verbose)?;
+        if !cli.quiet {
+            eprintln!("Followed imports from {} entry file(s) to {} file(s).", cli.follow_imports.len(), files.len());
+        }
+        files
+    };
+    // Followed files get the same checks as walked ones, against the path argument holding them
+    for file_path in followed {
+        if cli.auto_exclude_common && should_auto_exclude(&file_path, &auto_excludes) {
+            if cli.verbose {
+                eprintln!("Auto-excluded: {}", file_path.display());
+            }
+            excluded.record(Exclusion::AutoExcluded, &file_path);
+            continue;
+        }
+        if modified_cutoff.is_some_and(|cutoff| !is_modified_since(&file_path, cutoff, cli.verbose)) {
+            if cli.verbose {
+                eprintln!("Excluded by modification time: {}", file_path.display());
+            }
+            excluded.record(Exclusion::ModifiedTime, &file_path);
+            continue;
+        }
+        let root = state.paths.iter().find(|root| file_path.starts_with(root)).map_or(Path::new("."), PathBuf::as_path);
+        if filter.admit(&file_path, root, excluded) {
+            candidates.push(file_path);
+        }
+    }
+
+    let walked_paths: &[PathBuf] = if cli.follow_imports.is_empty() { &state.paths } else { &[] };
+    for path in walked_paths {
+        if cli.verbose {
+            eprintln!("Walking path: {}", path.display());
+        }
+
+        let mut walker = build_walker(path, cli);
+        walker.max_depth(walk_depth(path, cli, state));
+
+        let mut found_files = std::collections::HashSet::new();
+        // Everything the ignore rules let through, including files dropped by auto-exclude
+        let mut walked_files = HashSet::new();
+
+        for file_path in walk_files_parallel(&walker, cli.verbose, cli.verbose) {
+            if cli.report_excluded {
+                walked_files.insert(file_path.clone());
+            }
+            
+            if cli.auto_exclude_common && should_auto_exclude(&file_path, &auto_excludes) {
+                if cli.verbose {
+                    eprintln!("Auto-excluded: {}", file_path.display());
+                }
+                excluded.record(Exclusion::AutoExcluded, &file_path);
+                continue;
+            }
+            
+            found_files.insert(file_path);
+        }
+
+        if !unignore_patterns.is_empty() {
+            let mut walker_no_ignore = unignored_walker(path, cli);
+            walker_no_ignore.max_depth(walk_depth(path, cli, state));
+            for file_path in walk_files_parallel(&walker_no_ignore, cli.verbose, false) {
+                if found_files.contains(&file_path) || !should_unignore_file(&file_path, &unignore_patterns, cli.verbose) {
+                    continue;
+                }
+                
+                if cli.auto_exclude_common
+                    && should_auto_exclude(&file_path, &auto_excludes)
+                    && !unignore_names_file(&file_path, &unignore_patterns) {
+                    if cli.verbose {
+                        eprintln!("Auto-excluded despite --unignore (name the file without wildcards to keep it): {}", file_path.display());
+                    }
+                    continue;
+                }
+                
+                found_files.insert(file_path);
+            }
+        }
+
+        if cli.report_excluded {
+            // The ignore crate never yields the entries it skips, so walking again without
+            // ignore files is the only way to learn what they hid. --report-excluded's help
+            // names this cost.
+            let mut walker_unfiltered = WalkBuilder::new(path);
+            walker_unfiltered
+                .standard_filters(false)
+                .hidden(!cli.hidden)
+                .max_depth(walk_depth(path, cli, state))
+                .follow_links(cli.follow_symlinks);
+            walker_unfiltered.filter_entry(|entry| !is_vcs_metadata_dir(entry.path()));
+            
+            for file_path in walk_files_parallel(&walker_unfiltered, false, false) {
+                if !walked_files.contains(&file_path) && !found_files.contains(&file_path) {
+                    excluded.record(Exclusion::IgnoreFile, &file_path);
+                }
+            }
+        }
+
+        let mut file_paths: Vec<_> = found_files.into_iter().collect();
+        file_paths.sort();
+
+        if let Some(cutoff) = modified_cutoff {
+            file_paths.retain(|file_path| {
+                let keep = is_modified_since(file_path, cutoff, cli.verbose);
+                if !keep {
+                    if cli.verbose {
+                        eprintln!("Excluded by modification time: {}", file_path.display());
+                    }
+                    excluded.record(Exclusion::ModifiedTime, file_path);
+                }
+                keep
+            });
+        }
+
+        for file_path in file_paths {
+            if filter.admit(&file_path, path, excluded) {
+                candidates.push(file_path);
+            }
+        }
+    }
+
+    // Overlapping arguments such as `README.md .` would otherwise emit the same file twice
+    let mut seen_files = HashSet::new();
+    candidates.retain(|file_path| seen_files.insert(file_path.canonicalize().unwrap_or_else(|_| file_path.clone())));
+
+    if !cli.no_warn_secrets && !cli.quiet {
+        warn_secret_files(&candidates);
+    }
+
+    if filter.files_omitted_by_cap > 0 && !cli.quiet {
+        eprintln!("File cap of {} reached; omitted {} file(s).", 
+                 cli.max_files.unwrap_or_default(), filter.files_omitted_by_cap);
+    }
+
+    Ok(candidates)
+}
+
+// Estimates each processed file once, for the sorting, budgets and reports that follow
+fn count_tokens(files: &[(PathBuf, String)], state: &mut RunState) {
+    state.token_counts = files.iter()
+        .filter(|(path, _)| !state.referenced_files.contains_key(path))
+        .map(|(path, content)| (path.clone(), estimate_tokens(content)))
+        .collect();
+}
+
+// Below this size rounding alone can push a file's tokens per byte out of range
+const TOKEN_SANITY_MIN_BYTES: usize = 256;
+
+// Real tokenizers land between these for text and code alike, so counts outside them point at
+// the heuristic going wrong on the file, and at --max-tokens misjudging it
+const PLAUSIBLE_TOKENS_PER_BYTE: std::ops::RangeInclusive<f64> = 0.1..=1.0;
+
+fn warn_implausible_token_counts(files: &[(PathBuf, String)], state: &RunState) {
+    for (path, content) in files {
+        if content.len() < TOKEN_SANITY_MIN_BYTES || state.referenced_files.contains_key(path) {
+            continue;
+        }
+        let tokens = file_tokens(path, content, state);
+        let tokens_per_byte = tokens as f64 / content.len() as f64;
+        if !PLAUSIBLE_TOKENS_PER_BYTE.contains(&tokens_per_byte) {
+            eprintln!("Warning: Token estimate for {} looks implausible: ~{} tokens for {} bytes ({:.2} tokens/byte)", 
+                     path.display(), tokens, content.len(), tokens_per_byte);
+        }
+    }
+}
+
+// Token count of a processed file, from `count_tokens` when available
+fn file_tokens(path: &Path, content: &str, state: &RunState) -> usize {
+    state.token_counts.get(path).copied().unwrap_or_else(|| estimate_tokens(content))
+}
+
+const DEFAULT_PROGRESS_TEMPLATE: &str = "Reading {bar:30} {bytes}/{total_bytes} (ETA {eta})";
+const FILES_PROGRESS_TEMPLATE: &str = "Files   {bar:30} {pos}/{len}";
+
+// The files and bytes bars shown while reading candidates
+struct ReadProgress {
+    multi: indicatif::MultiProgress,
+    files: indicatif::ProgressBar,
+    bytes: indicatif::ProgressBar,
+    // Each candidate's size on disk, read once for the total
+    sizes: Vec<u64>,
+}
+
+impl ReadProgress {
+    // Counts the `index`th candidate as read
+    fn advance(&self, index: usize) {
+        self.files.inc(1);
+        self.bytes.inc(self.sizes[index]);
+    }
+    
+    fn finish(self) {
+        self.files.finish_and_clear();
+        self.bytes.finish_and_clear();
+        let _ = self.multi.clear();
+    }
+}
+
+// Progress for reading `candidates`: a file count, and bytes read against their total size on
+// disk, since file sizes vary far too much for the count alone to give a useful ETA. With
+// --progress auto it is only shown on a terminal and without FCLIP_NO_PROGRESS; never with
+// --quiet or --verbose (whose per-file lines would tear through the bars).
+fn read_progress(candidates: &[PathBuf], cli: &Config) -> Option<ReadProgress> {
+    use std::io::IsTerminal;
+    
+    if cli.quiet || cli.verbose {
+        return None;
+    }
+    let draw_target = match cli.progress {
+        ProgressMode::Never => return None,
+        ProgressMode::Auto if std::env::var_os("FCLIP_NO_PROGRESS").is_some_and(|value| !value.is_empty()) => return None,
+        ProgressMode::Auto if !std::io::stderr().is_terminal() => return None,
+        ProgressMode::Auto => indicatif::ProgressDrawTarget::stderr(),
+        // indicatif hides its stderr target off a terminal, which `always` has to get around
+        ProgressMode::Always => indicatif::ProgressDrawTarget::term_like(Box::new(console::Term::stderr())),
+    };
+    
+    let sizes: Vec<u64> = candidates.iter()
+        .map(|path| fs::metadata(path).map_or(0, |metadata| metadata.len()))
+        .collect();
+    let template = cli.progress_template.as_deref().unwrap_or(DEFAULT_PROGRESS_TEMPLATE);
+    let bytes_style = indicatif::ProgressStyle::with_template(template).unwrap_or_else(|e| {
+        eprintln!("Warning: Invalid --progress-template ({}); using the default", e);
+        indicatif::ProgressStyle::with_template(DEFAULT_PROGRESS_TEMPLATE).unwrap_or_else(|_| indicatif::ProgressStyle::default_bar())
+    });
+    let files_style = indicatif::ProgressStyle::with_template(FILES_PROGRESS_TEMPLATE).unwrap_or_else(|_| indicatif::ProgressStyle::default_bar());
+    
+    let multi = indicatif::MultiProgress::with_draw_target(draw_target);
+    let files = multi.add(indicatif::ProgressBar::new(candidates.len() as u64).with_style(files_style));
+    let bytes = multi.add(indicatif::ProgressBar::new(sizes.iter().sum()).with_style(bytes_style));
+    Some(ReadProgress { multi, files, bytes, sizes })
+}
+
+// Reads `candidates`, then orders them and keeps those that fit the size and token budgets.
+// Returns the files with their total size and tokens.
+fn read_candidates(candidates: &[PathBuf], cli: &Config, state: &mut RunState, processing: &ProcessingOptions, pin_patterns: &[Pattern], excluded: &mut ExclusionReport) -> (Vec<(PathBuf, String)>, usize, usize) {
+    let progress = read_progress(candidates, cli);
+    let mut processed = Vec::with_capacity(candidates.len());
+    for (index, file_path) in candidates.iter().enumerate() {
+        if let Some(progress) = &progress {
+            progress.advance(index);
+        }
+        match process_single_file(file_path, cli, state, processing) {
+            Ok(content) => processed.push((file_path.clone(), content)),
+            Err(reason) => {
+                excluded.record(reason, file_path);
+                if reason == Exclusion::AboveMaxSize && cli.reference_oversized {
+                    reference_file(file_path, 0, state, &mut processed);
+                }
+            }
+        }
+    }
+    
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    
+    count_tokens(&processed, state);
+    if cli.verbose {
+        warn_implausible_token_counts(&processed, state);
+    }
+    
+    // Sorting before the budget checks means the files that come first also get budget priority
+    sort_files(&mut processed, cli, state);
+    pin_files(&mut processed, pin_patterns, &state.paths);
+    
+    state.binary_files = std::mem::take(&mut *processing.binary_files.lock().unwrap_or_else(|e| e.into_inner()));
+    if !cli.dedup {
+        return select_within_budget(processed, cli, state, processing, excluded);
+    }
+    
+    // Deduplicating first means copies only cost their reference line against the budgets.
+    // Sorting and pinning already happened, so the copy that's kept is the one listed first.
+    let full_tokens: HashMap<PathBuf, usize> = processed.iter()
+        .map(|(path, content)| (path.clone(), file_tokens(path, content, state)))
+        .collect();
+    state.duplicates = deduplicate_files(&mut processed, cli, state);
+    state.token_counts.retain(|path, _| !state.duplicates.contains_key(path));
+    let (mut files_data, mut total_size_bytes, mut total_tokens) = select_within_budget(processed, cli, state, processing, excluded);
+    
+    // A reference to a file the budgets cut would point at nothing, and the copy wouldn't
+    // have fit either
+    let selected: HashSet<PathBuf> = files_data.iter().map(|(path, _)| path.clone()).collect();
+    let orphaned: Vec<PathBuf> = files_data.iter()
+        .filter(|(path, _)| state.duplicates.get(path).is_some_and(|original| !selected.contains(original)))
+        .map(|(path, _)| path.clone())
+        .collect();
+    for path in &orphaned {
+        excluded.record(Exclusion::TokenBudget, path);
+        state.duplicates.remove(path);
+    }
+    files_data.retain(|(path, content)| {
+        let keep = !orphaned.contains(path);
+        if !keep {
+            total_size_bytes -= content.len();
+            total_tokens -= file_tokens(path, content, state);
+        }
+        keep
+    });
+    
+    if !state.duplicates.is_empty() && !cli.quiet {
+        let saved: usize = files_data.iter()
+            .filter(|(path, _)| state.duplicates.contains_key(path))
+            .map(|(path, content)| full_tokens[path].saturating_sub(file_tokens(path, content, state)))
+            .sum();
+        eprintln!("Deduplicated {} file(s), saving ~{} tokens.", state.duplicates.len(), saved);
+    }
+    (files_data, total_size_bytes, total_tokens)
+}
+
+fn format_name(format: &OutputFormat) -> String {
+    clap::ValueEnum::to_possible_value(format).map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+// Splits an --emit target at its first colon, so Windows paths such as `json:C:\out.json` work
+fn parse_emit_targets(emit: &[String]) -> Result<Vec<(OutputFormat, PathBuf)>> {
+    emit.iter()
+        .map(|target| {
+            let (format, path) = target.split_once(':')
+                .filter(|(_, path)| !path.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("Invalid --emit '{}' (expected FORMAT:PATH, e.g. 'json:out.json')", target))?;
+            let format = <OutputFormat as clap::ValueEnum>::from_str(format, true)
+                .map_err(|_| anyhow::anyhow!("Unknown --emit format '{}' (expected default, markdown, json, ndjson or raw)", format))?;
+            Ok((format, PathBuf::from(path)))
+        })
+        .collect()
+}
+
+/// Collects, formats and writes the output for one invocation, printing the usual summary.
+/// This is one collect/format/copy pass; [`watch`] calls it again after every change.
+pub fn run(cli: &Config) -> Result<()> {
+    let write_to_stdout = cli.stdout || cli.output_file.as_deref() == Some(Path::new("-"));
+    let output_file = if write_to_stdout {
+        None
+    } else {
+        cli.output_file.as_deref().map(|path| compressed_output_path(path, cli))
+    };
+    if output_compression(cli) != OutputCompression::None && output_file.is_none() {
+        anyhow::bail!("--gzip and --compress-output only apply to --output-file, not to clipboard or stdout output");
+    }
+    Codec::from_cli(cli)?;
+    if cli.manifest && output_file.is_none() {
+        anyhow::bail!("--manifest only applies to --output-file, not to clipboard or stdout output");
+    }
+    let target = if write_to_stdout {
+        OutputTarget::Stdout
+    } else if let Some(path) = &output_file {
+        OutputTarget::File(path)
+    } else if cli.osc52 || std::env::var_os("SSH_TTY").is_some() {
+        OutputTarget::Osc52
+    } else {
+        OutputTarget::Clipboard
+    };
+    
+    let emit_targets = parse_emit_targets(&cli.emit)?;
+    
+    let mut excluded = ExclusionReport::default();
+    let processing = ProcessingOptions::from_cli(cli)?;
+    let pin_patterns = parse_glob_patterns(&cli.pin)?;
+    let cost_rate = resolve_cost_rate(cli)?;
+    let context_model = cli.model.as_deref().map(find_model).transpose()?;
+    let state = &mut RunState::new(cli)?;
+    let mut candidates = find_candidates(cli, state, output_file.as_deref(), &mut excluded)?;
+    state.structure_only_files = excluded.paths(Exclusion::ContentDepth);
+
+    if cli.interactive && !candidates.is_empty() {
+        match picker::pick_files(&candidates)? {
+            Some(selected) => {
+                for file_path in candidates.iter().filter(|path| !selected.contains(path)) {
+                    excluded.record(Exclusion::NotSelected, file_path);
+                }
+                candidates = selected;
+            }
+            None => {
+                if !cli.quiet {
+                    eprintln!("Selection cancelled; nothing copied.");
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    if cli.tree_only {
+        if candidates.is_empty() {
+            if !cli.quiet {
+                eprintln!("No files found matching the criteria.");
+            }
+            excluded.report(cli);
+            return Ok(());
+        }
+        
+        let tree_entries: Vec<TreeEntry> = candidates.iter()
+            .chain(&state.structure_only_files)
+            .map(|path| TreeEntry {
+                path,
+                size: if cli.tree_sizes { fs::metadata(path).map(|m| m.len() as usize).unwrap_or(0) } else { 0 },
+                tokens: None,
+            })
+            .collect();
+        let tree = generate_directory_tree(&state.paths, &tree_entries, cli);
+        let tree = if output_crlf(cli) { to_crlf(&tree) } else { tree };
+        
+        if cli.dry_run {
+            eprintln!("=== DRY RUN - Would copy structure of {} file(s) ===", candidates.len());
+            eprint!("{}", tree);
+            excluded.report(cli);
+            return Ok(());
+        }
+        
+        let written = write_output(&tree, target, cli)?;
+        let summary = format!("structure of {} file(s) (~{} tokens).", candidates.len(), estimate_tokens(&tree));
+        if !cli.quiet {
+            match written {
+                OutputTarget::Stdout => eprintln!("Wrote {}", summary),
+                OutputTarget::File(_) => eprintln!("Processed {}", summary),
+                OutputTarget::Clipboard => eprintln!("Copied {}", summary),
+                OutputTarget::Osc52 => eprintln!("Copied via OSC 52: {}", summary),
+            }
+        }
+        excluded.report(cli);
+        return Ok(());
+    }
+
+    let (files_data, total_size_bytes, total_tokens) = read_candidates(&candidates, cli, state, &processing, &pin_patterns, &mut excluded);
+
+    if cli.fingerprint && !files_data.is_empty() {
+        state.content_fingerprint = Some(content_fingerprint(&files_data, state));
+    }
+
+    if !files_data.is_empty() {
+        if cli.dry_run {
+            eprintln!("=== DRY RUN - Would copy {} file(s) ({:.1} KB, ~{} tokens) ===", 
+                     files_data.len(), total_size_bytes as f64 / 1024.0, total_tokens);
+            
+            for (path, content) in &files_data {
+                let lines = content.lines().count();
+                let tokens = file_tokens(path, content, state);
+                eprintln!("  {} ({} lines, {} bytes, ~{} tokens)", 
+                         styled_path(path, cli), lines, content.len(), tokens);
+            }
+            
+            print_section_previews(&files_data, cli, state);
+            let output_tokens = estimate_tokens(&format_output(&files_data, &cli.format, cli, state));
+            eprintln!("Would produce ~{} output tokens.", output_tokens);
+            for (format, path) in &emit_targets {
+                eprintln!("Would also write {} output to {}.", format_name(format), path.display());
+            }
+            
+            report_processing(&processing, cli);
+            
+            if let Some(fingerprint) = &state.content_fingerprint {
+                eprintln!("Fingerprint: {}", fingerprint);
+            }
+            
+            if let Some((label, rate)) = &cost_rate {
+                eprintln!("Estimated cost: ${:.4} ({} at ${:.2}/M input tokens)", 
+                         total_tokens as f64 / 1_000_000.0 * rate, label, rate);
+            }
+            
+            if cli.stats {
+                print_stats(&files_data, total_size_bytes, total_tokens, cli, state)?;
+            }
+            
+            if cli.budget_report {
+                print_budget_report(&files_data, cli, state);
+            }
+            
+            check_context_window(output_tokens, context_model, cli)?;
+        } else {
+            if let OutputTarget::File(path) = target {
+                if cli.manifest {
+                    write_manifest(&files_data, path, cli, state)?;
+                }
+            }
+            
+            // Only a single plain file can be written piece by piece; every other target
+            // needs the whole output at once
+            let (written, output_tokens) = match target {
+                OutputTarget::File(path) if cli.split_by_size.is_none() => {
+                    (target, write_output_streaming(&files_data, cli, state, path)?)
+                }
+                _ => {
+                    let formatted_output = format_output(&files_data, &cli.format, cli, state);
+                    if would_clear_clipboard(&formatted_output, target) {
+                        if !cli.quiet {
+                            eprintln!("Warning: The {} selected file(s) produced no output; the clipboard was left unchanged.", files_data.len());
+                        }
+                        report_processing(&processing, cli);
+                        excluded.report(cli);
+                        return Ok(());
+                    }
+                    let output_tokens = estimate_tokens(&formatted_output);
+                    (write_output(&formatted_output, target, cli)?, output_tokens)
+                }
+            };
+            
+            // The extra formats reuse the files already read, so nothing is walked twice
+            for (format, path) in &emit_targets {
+                let formatted_output = format_output(&files_data, format, cli, state);
+                write_file(path, &formatted_output, false, Codec { compression: OutputCompression::None, level: 0 })?;
+                if !cli.quiet {
+                    eprintln!("Also wrote {} output to: {}", format_name(format), path.display());
+                }
+            }
+            
+            let summary = format!("{} file(s) ({:.1} KB, ~{} tokens -> ~{} output tokens).", 
+                                  files_data.len(), total_size_bytes as f64 / 1024.0, total_tokens, output_tokens);
+            if !cli.quiet {
+                match written {
+                    OutputTarget::Stdout => eprintln!("Wrote content of {}", summary),
+                    OutputTarget::File(_) => eprintln!("Processed {}", summary),
+                    OutputTarget::Clipboard => eprintln!("Copied content of {}", summary),
+                    OutputTarget::Osc52 => eprintln!("Copied via OSC 52: content of {}", summary),
+                }
+            }
+            
+            report_processing(&processing, cli);
+            
+            if let Some(fingerprint) = &state.content_fingerprint {
+                eprintln!("Fingerprint: {}", fingerprint);
+            }
+            
+            if let Some((label, rate)) = &cost_rate {
+                eprintln!("Estimated cost: ${:.4} ({} at ${:.2}/M input tokens)", 
+                         total_tokens as f64 / 1_000_000.0 * rate, label, rate);
+            }
+            
+            if cli.stats {
+                print_stats(&files_data, total_size_bytes, total_tokens, cli, state)?;
+            }
+            
+            if cli.budget_report {
+                print_budget_report(&files_data, cli, state);
+            }
+            
+            check_context_window(output_tokens, context_model, cli)?;
+        }
+    } else {
+        if !cli.quiet {
+            eprintln!("No files found matching the criteria.");
+        }
+        report_processing(&processing, cli);
+    }
+    
+    excluded.report(cli);
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("24h").unwrap(), Duration::from_secs(24 * 60 * 60));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert!(parse_duration("7y").is_err());
+        assert!(parse_duration("d").is_err());
+    }
+
+    #[test]
+    fn modified_within_excludes_older_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, age_days) in [("fresh.rs", 1), ("stale.rs", 30)] {
+            let path = dir.path().join(name);
+            fs::write(&path, "fn main() {}\n").unwrap();
+            let mtime = now - Duration::from_secs(age_days * 24 * 60 * 60);
+            filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(mtime)).unwrap();
+        }
+
+        let cutoff = now - parse_duration("7d").unwrap();
+        assert!(is_modified_since(&dir.path().join("fresh.rs"), cutoff, false));
+        assert!(!is_modified_since(&dir.path().join("stale.rs"), cutoff, false));
+
+        let config = Config::parse_from(["fclip", "--quiet", "--modified-within", "7d", dir.path().to_str().unwrap()]);
+        let files = collect_files(&config).unwrap().files;
+        let names: Vec<_> = files.iter().map(|(path, _)| path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["fresh.rs"]);
+    }
+
+    #[test]
+    fn unreadable_mtime_is_included() {
+        assert!(is_modified_since(Path::new("does/not/exist.rs"), SystemTime::now(), false));
+    }
+
+    #[test]
+    fn dedup_happens_before_the_token_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let license = "Permission is hereby granted, free of charge, to any person obtaining a copy. ".repeat(5);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.path().join(name), &license).unwrap();
+        }
+        fs::write(dir.path().join("d.txt"), "Something else entirely, written out in plain prose. ".repeat(7)).unwrap();
+        let copy_tokens = estimate_tokens(&license);
+        let budget = (2 * copy_tokens + 40).to_string();
+
+        let config = Config::parse_from(["fclip", "--quiet", "--dedup", "--max-tokens", &budget, dir.path().to_str().unwrap()]);
+        let collection = collect_files(&config).unwrap();
+        let files = &collection.files;
+        let names: Vec<_> = files.iter().map(|(path, _)| path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["a.txt", "b.txt", "c.txt", "d.txt"]);
+        assert_eq!(files[1].1, format!("(identical to {})\n", styled_path(&files[0].0, &config)));
+        assert_eq!(collection.state.duplicates.len(), 2);
+    }
+
+    #[test]
+    fn auto_exclude_matches_whole_components() {
+        let defaults = resolve_auto_excludes(&Config::parse_from(["fclip"]));
+        assert!(should_auto_exclude(Path::new("node_modules/left-pad/index.js"), &defaults));
+        assert!(should_auto_exclude(Path::new("web/app/node_modules/react/index.js"), &defaults));
+        assert!(should_auto_exclude(Path::new("build/out.js"), &defaults));
+        assert!(should_auto_exclude(Path::new("logs/server.log"), &defaults));
+        assert!(!should_auto_exclude(Path::new("docs/my_node_modules_notes.txt"), &defaults));
+        assert!(!should_auto_exclude(Path::new("src/rebuild.rs"), &defaults));
+        assert!(!should_auto_exclude(Path::new("frontend/main.ts"), &defaults));
+    }
+
+    #[test]
+    fn auto_exclude_overrides_remove_and_add_defaults() {
+        let config = Config::parse_from(["fclip", "--auto-exclude-remove", "Vendor", "--auto-exclude-add", "fixtures"]);
+        let excludes = resolve_auto_excludes(&config);
+        assert!(!should_auto_exclude(Path::new("vendor/lib.go"), &excludes));
+        assert!(should_auto_exclude(Path::new("tests/fixtures/big.json"), &excludes));
+        assert!(should_auto_exclude(Path::new("node_modules/x.js"), &excludes));
+
+        let config = Config::parse_from(["fclip", "--auto-exclude-list", "tmp,*.bak"]);
+        let excludes = resolve_auto_excludes(&config);
+        assert!(!should_auto_exclude(Path::new("node_modules/x.js"), &excludes));
+        assert!(should_auto_exclude(Path::new("src/old.rs.bak"), &excludes));
+    }
+
+    #[test]
+    fn cargo_dependencies_handle_subtables_and_inline_tables() {
+        let manifest: toml::Table = toml::from_str(r#"
+            [package]
+            name = "demo"
+
+            [dependencies]
+            anyhow = "1.0"
+            serde = { version = "1.0", features = [
+                "derive",
+                "rc",
+            ] }
+            local = { path = "../local" }
+            shared = { workspace = true }
+            fork = { git = "https://example.com/fork.git", branch = "main" }
+
+            [dependencies.tokio]
+            version = "1.35"
+            features = ["full"]
+
+            [dev-dependencies]
+            tempfile = "3"
+
+            [build-dependencies.cc]
+            version = "1.0"
+        "#).unwrap();
+
+        assert_eq!(format_cargo_dependencies(&manifest), "\
+- anyhow: 1.0
+- fork: git https://example.com/fork.git (branch main)
+- local: path ../local
+- serde: 1.0
+- shared: workspace
+- tokio: 1.35
+- tempfile: 3 (dev)
+- cc: 1.0 (build)
+");
+    }
+
+    #[test]
+    fn apostrophes_in_comments_do_not_stop_collapsing() {
+        assert_eq!(compress_line("// it's   fine,   really"), "// it's fine, really");
+        assert_eq!(compress_line("# don't   do   this   ( it's   slow )"), "# don't do this ( it's slow )");
+        // An unmatched quote never opens a string
+        assert_eq!(compress_line("x  =  5  # 5'   is   the   height"), "x = 5 # 5' is the height");
+    }
+
+    #[test]
+    fn quoted_code_keeps_its_whitespace() {
+        assert_eq!(compress_line("name  =  'a    b'  +  \"c    d\""), "name = 'a    b' + \"c    d\"");
+        assert_eq!(compress_line("msg  =  \"it's    here\"  ;"), "msg = \"it's    here\" ;");
+        assert_eq!(compress_line("let  c  =  '\\''  ;  let  d  =  'x' ;"), "let c = '\\'' ; let d = 'x' ;");
+        assert_eq!(compress_content("    if  x  {\n\n\n        y  }\n", "rs", 2), "    if x {\n\n        y }\n");
+    }
+
+    #[test]
+    fn markdown_fences_survive_compression() {
+        let markdown = "# Example\n\nSome   prose   with    gaps.\n\n\n```python\ndef f(x):\n    if x:\n\n\n        return   x\n```\n\nMore    prose.\n";
+        assert_eq!(compress_content(markdown, "md", 2),
+            "# Example\n\nSome prose with gaps.\n\n```python\ndef f(x):\n    if x:\n\n\n        return   x\n```\n\nMore prose.\n");
+        // Blank-line removal at level 3 leaves the fenced block alone too
+        assert!(compress_content(markdown, "md", 3).contains("    if x:\n\n\n        return   x\n"));
+        // Outside markdown the same text is just code
+        assert!(compress_content(markdown, "txt", 2).contains("    if x:\n\n        return x\n"));
+    }
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if little_endian { unit.to_le_bytes() } else { unit.to_be_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn bomless_utf16_is_detected_as_text() {
+        let text = "fn main() {\n    println!(\"hello\");\n}\n";
+        assert_eq!(detect_bomless_utf16(&utf16(text, true)), Some(encoding_rs::UTF_16LE));
+        assert_eq!(detect_bomless_utf16(&utf16(text, false)), Some(encoding_rs::UTF_16BE));
+        assert_eq!(detect_bomless_utf16(text.as_bytes()), None);
+        // Odd lengths can't be UTF-16
+        assert_eq!(detect_bomless_utf16(&utf16(text, true)[1..]), None);
+    }
+
+    #[test]
+    fn real_binary_is_rejected() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend((0..=255u8).cycle().take(1000));
+        assert!(is_likely_binary(&png, 30.0));
+        assert_eq!(detect_bomless_utf16(&png), None);
+    }
+
+    #[test]
+    fn non_printable_threshold_is_exclusive() {
+        let sample = |control: usize| [vec![0x01u8; control], vec![b'a'; 100 - control]].concat();
+        assert!(!is_likely_binary(&sample(30), 30.0));
+        assert!(is_likely_binary(&sample(31), 30.0));
+        assert!(!is_likely_binary(&sample(31), 50.0));
+        // Tabs and line endings aren't control noise
+        assert!(!is_likely_binary(&b"\t\t\r\n".repeat(25), 0.0));
+    }
+
+    #[test]
+    fn null_tolerant_reads_bomless_utf16_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), utf16("café notes saved by a Windows editor\n", true)).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let config = Config::parse_from(["fclip", "--quiet", root]);
+        assert!(collect_files(&config).unwrap().files.is_empty());
+
+        let config = Config::parse_from(["fclip", "--quiet", "--binary-null-tolerant", root]);
+        let files = collect_files(&config).unwrap().files;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].1, "café notes saved by a Windows editor\n");
+    }
+
+    #[test]
+    fn fence_languages_cover_common_extensions() {
+        for (ext, lang) in [
+            ("rs", "rust"), ("py", "python"), ("jsx", "jsx"), ("tsx", "tsx"), ("go", "go"),
+            ("java", "java"), ("c", "c"), ("cpp", "cpp"), ("sql", "sql"), ("kt", "kotlin"),
+            ("rb", "ruby"), ("php", "php"), ("swift", "swift"), ("YML", "yaml"), ("xyz", ""),
+        ] {
+            assert_eq!(lang_for_ext(ext), lang, "extension {}", ext);
+        }
+        assert_eq!(lang_for_path(Path::new("Dockerfile"), ""), "dockerfile");
+        assert_eq!(lang_for_path(Path::new("bin/tool"), "#!/usr/bin/env python3\n"), "python");
+    }
+
+    #[test]
+    fn grouped_and_flat_markdown_use_the_same_fences() {
+        let files: Vec<(PathBuf, String)> = ["src/app.tsx", "src/lib.rs", "db/schema.sql", "Main.kt", "README.md"]
+            .iter()
+            .map(|path| (PathBuf::from(path), "content\n".to_string()))
+            .collect();
+        let fences = |grouped: bool| {
+            let mut args = vec!["fclip", "--format", "markdown"];
+            if grouped {
+                args.push("--group-by-type");
+            }
+            let output = format_output(&files, &OutputFormat::Markdown, &Config::parse_from(args), &RunState::default());
+            let mut fences: Vec<String> = output.lines()
+                .filter(|line| line.starts_with("```") && line.len() > 3)
+                .map(str::to_string)
+                .collect();
+            fences.sort();
+            fences
+        };
+        assert_eq!(fences(false), ["```kotlin", "```markdown", "```rust", "```sql", "```tsx"]);
+        assert_eq!(fences(true), fences(false));
+    }
+
+    #[test]
+    fn dry_run_selects_what_a_real_run_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, lines) in [("b.rs", 40), ("a.rs", 5), ("c/d.rs", 80), ("c/e.rs", 10)] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "let value = compute(42);\n".repeat(lines)).unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+        let args = ["fclip", "--quiet", "--sort", "size", "--max-tokens", "600", root];
+
+        let dry = Config::parse_from(args.iter().copied().chain(["--dry-run"]));
+        let dry_selection: Vec<PathBuf> = collect_files(&dry).unwrap().files.into_iter().map(|(path, _)| path).collect();
+
+        let out = tempfile::tempdir().unwrap();
+        let output_file = out.path().join("out.txt");
+        let real = Config::parse_from(args.iter().copied().chain(["--output-file", output_file.to_str().unwrap()]));
+        run(&real).unwrap();
+        let written: Vec<PathBuf> = fs::read_to_string(&output_file).unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("--- ")?.strip_suffix(" ---"))
+            .map(PathBuf::from)
+            .collect();
+
+        assert!(!dry_selection.is_empty() && dry_selection.len() < 4, "the budget should cut something");
+        assert_eq!(dry_selection, written);
+    }
+
+    #[test]
+    fn append_adds_to_a_small_output_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.txt");
+        let plain = Codec { compression: OutputCompression::None, level: 0 };
+        write_output_chunks("first\n", &output, 1024, true, plain, true).unwrap();
+        write_output_chunks("second\n", &output, 1024, true, plain, true).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "first\nsecond\n");
+        assert!(!dir.path().join("dump_part_001.txt").exists());
+    }
+
+    #[test]
+    fn chunked_append_starts_a_new_numbered_series() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.txt");
+        let plain = Codec { compression: OutputCompression::None, level: 0 };
+        write_output_chunks("aaaabbbbcc", &output, 4, false, plain, true).unwrap();
+        write_output_chunks("ddddee", &output, 4, true, plain, true).unwrap();
+
+        let part = |number: usize| fs::read_to_string(dir.path().join(format!("dump_part_{:03}.txt", number))).unwrap();
+        assert_eq!([part(1), part(2), part(3), part(4), part(5)], ["aaaa", "bbbb", "cc", "dddd", "ee"]);
+        assert!(!output.exists());
+        let sums = fs::read_to_string(dir.path().join("dump.sha256sums")).unwrap();
+        assert_eq!(sums.lines().count(), 5);
+        assert!(sums.lines().last().unwrap().ends_with("  dump_part_005.txt"));
+
+        // Without --append-to-file the series starts over
+        write_output_chunks("ffffgg", &output, 4, false, plain, true).unwrap();
+        assert_eq!([part(1), part(2)], ["ffff", "gg"]);
+        assert_eq!(fs::read_to_string(dir.path().join("dump.sha256sums")).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn library_entry_points_resolve_like_the_cli() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        fs::write(&file, "one\ntwo\nthree\nfour\n").unwrap();
+        let ranged = format!("{}:2-3", file.display());
+
+        let config = Config::parse_from(["fclip", "--quiet", "--packed", ranged.as_str()]);
+        let collection = collect_files(&config).unwrap();
+        assert_eq!(collection.files, [(file.clone(), "two\nthree\n".to_string())]);
+        let packed = format(&collection, &config);
+        assert!(packed.contains(&format!("================ File: {}", file.display())));
+
+        // A second pass starts over from the same options
+        assert_eq!(collect_files(&config).unwrap().files, collection.files);
+
+        assert!(Config::from_args(["fclip", "--no-such-flag"]).is_err());
+    }
+
+    #[test]
+    fn unignore_respects_auto_exclude_unless_the_file_is_named() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "*.lock\n*.log\n").unwrap();
+        for name in ["Cargo.lock", "notes.lock", "debug.log", "main.rs"] {
+            fs::write(dir.path().join(name), "x\n").unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+        let selected = |extra: &[&str]| -> Vec<String> {
+            let args = ["fclip", "--quiet", "--auto-exclude-common"].iter().chain(extra).chain([&root]);
+            let config = Config::parse_from(args);
+            collect_files(&config).unwrap().files.into_iter()
+                .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(selected(&[]), ["main.rs"]);
+        // A wildcard brings back what .gitignore hid, but not what auto-exclude drops
+        assert_eq!(selected(&["--unignore", "*.lock"]), ["main.rs", "notes.lock"]);
+        assert_eq!(selected(&["--unignore", "*.log"]), ["main.rs"]);
+        // Naming the file overrides auto-exclude too
+        assert_eq!(selected(&["--unignore", "Cargo.lock"]), ["Cargo.lock", "main.rs"]);
+        // Without auto-exclude, --unignore '*.lock' brings back every lock file
+        let config = Config::parse_from(["fclip", "--quiet", "--unignore", "*.lock", root]);
+        assert_eq!(collect_files(&config).unwrap().files.len(), 3);
+    }
+
+    #[test]
+    fn path_depth_suffixes_override_the_global_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let src = dir.path().join("src");
+
+        let args = [format!("{}:1", docs.display()), src.display().to_string(), format!("{}:2", dir.path().join("notes.txt").display())];
+        let (paths, depths) = split_path_depths(&args.iter().map(PathBuf::from).collect::<Vec<_>>());
+        // Files and paths without a suffix are left alone
+        assert_eq!(paths, [docs.clone(), src.clone(), PathBuf::from(&args[2])]);
+        assert_eq!(depths, HashMap::from([(docs.clone(), 1)]));
+
+        let config = Config::parse_from(["fclip", "--depth", "5", &args[0], &args[1]]);
+        let state = RunState::new(&config).unwrap();
+        assert_eq!(walk_depth(&docs, &config, &state), Some(1));
+        assert_eq!(walk_depth(&src, &config, &state), Some(5));
+    }
+
+    #[test]
+    fn existing_paths_with_colons_are_not_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let odd = dir.path().join("v2:3");
+        fs::create_dir(&odd).unwrap();
+        let (paths, depths) = split_path_depths(std::slice::from_ref(&odd));
+        assert_eq!(paths, [odd]);
+        assert!(depths.is_empty());
+
+        let not_a_depth = dir.path().join("docs:deep");
+        let (paths, depths) = split_path_depths(std::slice::from_ref(&not_a_depth));
+        assert_eq!(paths, [not_a_depth]);
+        assert!(depths.is_empty());
+    }
+
+    #[test]
+    fn all_empty_files_never_reach_the_clipboard() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(dir.path().join(name), "  \n\n").unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+
+        let config = Config::parse_from(["fclip", "--quiet", "--exclude-empty", root]);
+        assert!(collect_files(&config).unwrap().files.is_empty());
+
+        let config = Config::parse_from(["fclip", "--quiet", "--format", "raw", "--compress", root]);
+        let collection = collect_files(&config).unwrap();
+        assert_eq!(collection.files.len(), 2);
+        let output = format(&collection, &config);
+        assert!(would_clear_clipboard(&output, OutputTarget::Clipboard));
+        assert!(would_clear_clipboard(&output, OutputTarget::Osc52));
+        // Files and stdout still get the (empty) output
+        assert!(!would_clear_clipboard(&output, OutputTarget::Stdout));
+        assert!(!would_clear_clipboard("--- a.txt ---\n", OutputTarget::Clipboard));
+    }
+
+    #[test]
+    fn preserve_bytes_keeps_bom_and_crlf() {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = b"\xEF\xBB\xBFline one\r\nline two  \r\n\r\nend";
+        fs::write(dir.path().join("win.txt"), bytes).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let config = Config::parse_from(["fclip", "--quiet", "--preserve-bytes", "--format", "raw", root]);
+        let collection = collect_files(&config).unwrap();
+        assert_eq!(collection.files[0].1.as_bytes(), bytes);
+        assert!(format(&collection, &config).as_bytes().starts_with(bytes));
+
+        let config = Config::parse_from(["fclip", "--quiet", root]);
+        let files = collect_files(&config).unwrap().files;
+        assert_eq!(files[0].1, "line one\nline two  \n\nend");
+    }
+
+    #[test]
+    fn zstd_compresses_each_chunk_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.txt.zst");
+        let codec = Codec { compression: OutputCompression::Zstd, level: 3 };
+        let content = "abcdefgh".repeat(3);
+        write_output_chunks(&content, &output, 10, false, codec, true).unwrap();
+
+        let part = |number: usize| zstd::decode_all(fs::File::open(dir.path().join(format!("dump.txt_part_{:03}.zst", number))).unwrap()).unwrap();
+        assert_eq!([part(1), part(2), part(3)].concat(), content.as_bytes());
+
+        // Appending adds a frame that decodes as part of the same stream
+        write_file(&output, "one\n", false, codec).unwrap();
+        write_file(&output, "two\n", true, codec).unwrap();
+        assert_eq!(zstd::decode_all(fs::File::open(&output).unwrap()).unwrap(), b"one\ntwo\n");
+    }
+
+    #[test]
+    fn followed_imports_go_through_the_file_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        let entry = file("main.js", "import a from './util';\nimport b from './.env';\nrequire('./out.txt');\nrequire('./skip.test.js');\n");
+        file("util.js", "export default 1;\n");
+        file(".env", "TOKEN=secret\n");
+        let output = file("out.txt", "previous dump\n");
+        file("skip.test.js", "test();\n");
+        let root = dir.path().to_str().unwrap();
+
+        let config = Config::parse_from(["fclip", "--quiet", "--block-secrets", "--exclude-glob", "*.test.js",
+            "--follow-imports", entry.to_str().unwrap(), root]);
+        let mut excluded = ExclusionReport::default();
+        let candidates = find_candidates(&config, &RunState::new(&config).unwrap(), Some(&output), &mut excluded).unwrap();
+        let names: Vec<_> = candidates.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["main.js", "util.js"]);
+        assert_eq!(excluded.paths(Exclusion::SecretFile), [dir.path().join(".env")]);
+        assert_eq!(excluded.paths(Exclusion::OutputFile), [output]);
+        assert_eq!(excluded.paths(Exclusion::ExcludeGlob), [dir.path().join("skip.test.js")]);
+    }
+
+    #[test]
+    fn config_file_values_obey_cli_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("fclip.toml");
+        fs::write(&config, "quiet = true\n").unwrap();
+        let config = config.to_str().unwrap();
+
+        let err = Config::from_args(["fclip", "--config", config, "--verbose"]).unwrap_err();
+        assert!(err.to_string().contains("--quiet cannot be used with --verbose"), "{}", err);
+        assert!(Config::from_args(["fclip", "--config", config]).is_ok());
+    }
+
+    #[test]
+    fn omitted_file_stubs_do_not_outlive_their_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("big.txt");
+        fs::write(&big, "x".repeat(500)).unwrap();
+        let config = Config::parse_from(["fclip", "--quiet", "--max-file-size", "100", "--reference-oversized",
+            dir.path().to_str().unwrap()]);
+
+        let collection = collect_files(&config).unwrap();
+        assert!(collection.state.referenced_files.contains_key(&big));
+        assert!(format(&collection, &config).contains("(omitted, 500 B)"));
+
+        // What --watch does after the file shrinks: another pass with the same Config
+        fs::write(&big, "small now\n").unwrap();
+        let collection = collect_files(&config).unwrap();
+        assert!(collection.state.referenced_files.is_empty());
+        assert_eq!(collection.files, [(big, "small now\n".to_string())]);
+    }
+
+    #[test]
+    fn chunks_never_split_a_character() {
+        let chunks = split_at_char_boundaries("ab\u{e9}\u{e9}cd", 3);
+        assert_eq!(chunks, ["ab", "\u{e9}", "\u{e9}c", "d"]);
+        assert_eq!(split_at_char_boundaries("\u{1f600}x", 2), ["\u{1f600}", "x"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.txt");
+        let plain = Codec { compression: OutputCompression::None, level: 0 };
+        let content = "caf\u{e9} ".repeat(20);
+        write_output_chunks(&content, &output, 7, false, plain, true).unwrap();
+        let mut parts: Vec<PathBuf> = fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        parts.sort();
+        let joined: String = parts.iter().map(|part| fs::read_to_string(part).unwrap()).collect();
+        assert_eq!(joined, content);
+        verify_chunks(dir.path(), true).unwrap();
+    }
+
+    #[test]
+    fn single_chunk_output_can_be_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dump.txt");
+        let plain = Codec { compression: OutputCompression::None, level: 0 };
+        write_output_chunks("first\n", &output, 1024, true, plain, true).unwrap();
+        write_output_chunks("second\n", &output, 1024, true, plain, true).unwrap();
+        let sums = fs::read_to_string(dir.path().join("dump.sha256sums")).unwrap();
+        assert_eq!(sums.lines().count(), 1);
+        verify_chunks(dir.path(), true).unwrap();
+
+        fs::write(&output, "tampered\n").unwrap();
+        assert!(verify_chunks(dir.path(), true).is_err());
+    }
+
+    #[test]
+    fn json_tree_nests_by_root_relative_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("README.md"), "# Demo\n").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        for style in ["relative", "absolute", "name-only"] {
+            let config = Config::parse_from(["fclip", "--quiet", "--format", "json", "--json-tree", "--path-style", style, root]);
+            let collection = collect_files(&config).unwrap();
+            let doc: OutputDoc = serde_json::from_str(&format(&collection, &config)).unwrap();
+            let tree = doc.tree.unwrap();
+            assert_eq!(tree.files.keys().collect::<Vec<_>>(), ["README.md"], "{}", style);
+            let main = &tree.dirs["src"].files["main.rs"];
+            assert_eq!(main.path, styled_path(&dir.path().join("src").join("main.rs"), &config));
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::io::{self, Stderr};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::estimate_tokens;
+
+struct Candidate {
+    path: PathBuf,
+    label: String,
+    tokens: usize,
+    selected: bool,
+}
+
+struct Picker {
+    candidates: Vec<Candidate>,
+    filter: String,
+    filtering: bool,
+    // Indices into `candidates` that match `filter`, in display order
+    visible: Vec<usize>,
+    list_state: ListState,
+}
+
+impl Picker {
+    fn new(paths: &[PathBuf]) -> Self {
+        let candidates = paths.iter()
+            .map(|path| Candidate {
+                path: path.clone(),
+                label: path.display().to_string(),
+                tokens: fs::read(path).map(|bytes| estimate_tokens(&String::from_utf8_lossy(&bytes))).unwrap_or(0),
+                selected: false,
+            })
+            .collect();
+
+        let mut picker = Self {
+            candidates,
+            filter: String::new(),
+            filtering: false,
+            visible: Vec::new(),
+            list_state: ListState::default(),
+        };
+        picker.refresh_filter();
+        picker
+    }
+
+    fn refresh_filter(&mut self) {
+        self.visible = self.candidates.iter()
+            .enumerate()
+            .filter(|(_, candidate)| fuzzy_matches(&candidate.label, &self.filter))
+            .map(|(i, _)| i)
+            .collect();
+
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.visible.len() - 1))
+        });
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.visible.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn toggle_current(&mut self) {
+        if let Some(&index) = self.list_state.selected().and_then(|i| self.visible.get(i)) {
+            self.candidates[index].selected = !self.candidates[index].selected;
+        }
+        self.move_cursor(1);
+    }
+
+    // Selects every visible file, or clears them all if they are already selected
+    fn toggle_visible(&mut self) {
+        let select = !self.visible.iter().all(|&i| self.candidates[i].selected);
+        for &i in &self.visible {
+            self.candidates[i].selected = select;
+        }
+    }
+
+    fn selected_totals(&self) -> (usize, usize) {
+        self.candidates.iter()
+            .filter(|candidate| candidate.selected)
+            .fold((0, 0), |(count, tokens), candidate| (count + 1, tokens + candidate.tokens))
+    }
+
+    fn into_selection(self) -> Vec<PathBuf> {
+        self.candidates.into_iter()
+            .filter(|candidate| candidate.selected)
+            .map(|candidate| candidate.path)
+            .collect()
+    }
+}
+
+// Case-insensitive subsequence match, so "srmain" finds "src/main.rs"
+fn fuzzy_matches(text: &str, pattern: &str) -> bool {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase);
+    pattern.chars()
+        .flat_map(char::to_lowercase)
+        .all(|wanted| text_chars.any(|c| c == wanted))
+}
+
+/// Shows a checklist of `paths` and returns the files the user selected, or `None` if the
+/// picker was cancelled. The UI is drawn on stderr so `--stdout` output stays clean.
+pub fn pick_files(paths: &[PathBuf]) -> Result<Option<Vec<PathBuf>>> {
+    let mut picker = Picker::new(paths);
+
+    terminal::enable_raw_mode()?;
+    let mut stderr = io::stderr();
+    if let Err(e) = execute!(stderr, EnterAlternateScreen) {
+        let _ = terminal::disable_raw_mode();
+        return Err(e.into());
+    }
+
+    let result = Terminal::new(CrosstermBackend::new(io::stderr()))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut terminal| run_picker(&mut terminal, &mut picker));
+
+    let _ = execute!(io::stderr(), LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+
+    Ok(if result? { Some(picker.into_selection()) } else { None })
+}
+
+// Returns true when the selection was confirmed, false when cancelled
+fn run_picker(terminal: &mut Terminal<CrosstermBackend<Stderr>>, picker: &mut Picker) -> Result<bool> {
+    loop {
+        terminal.draw(|frame| draw(frame, picker))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(false);
+        }
+
+        if picker.filtering {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => picker.filtering = false,
+                KeyCode::Backspace => {
+                    picker.filter.pop();
+                    picker.refresh_filter();
+                }
+                KeyCode::Char(c) => {
+                    picker.filter.push(c);
+                    picker.refresh_filter();
+                }
+                KeyCode::Up => picker.move_cursor(-1),
+                KeyCode::Down => picker.move_cursor(1),
+                _ => {}
+            }
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Enter => return Ok(true),
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+            KeyCode::Char(' ') => picker.toggle_current(),
+            KeyCode::Char('a') => picker.toggle_visible(),
+            KeyCode::Char('/') => picker.filtering = true,
+            KeyCode::Up | KeyCode::Char('k') => picker.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => picker.move_cursor(1),
+            KeyCode::PageUp => picker.move_cursor(-10),
+            KeyCode::PageDown => picker.move_cursor(10),
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, picker: &mut Picker) {
+    let [list_area, filter_area, status_area] = Layout::vertical([
+        Constraint::Min(1),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let items: Vec<ListItem> = picker.visible.iter()
+        .map(|&i| {
+            let candidate = &picker.candidates[i];
+            let mark = if candidate.selected { "[x]" } else { "[ ]" };
+            ListItem::new(format!("{} {} (~{} tokens)", mark, candidate.label, candidate.tokens))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Select files to copy "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, &mut picker.list_state);
+
+    let filter_line = if picker.filtering {
+        format!("/{}_", picker.filter)
+    } else if picker.filter.is_empty() {
+        String::new()
+    } else {
+        format!("filter: {} ({} of {} files)", picker.filter, picker.visible.len(), picker.candidates.len())
+    };
+    frame.render_widget(Paragraph::new(filter_line), filter_area);
+
+    let (count, tokens) = picker.selected_totals();
+    let status = Line::from(format!(
+        "{} selected, ~{} tokens | Space toggle  a all  / filter  Enter copy  Esc cancel",
+        count, tokens
+    )).style(Style::default().add_modifier(Modifier::BOLD));
+    frame.render_widget(Paragraph::new(status), status_area);
+}